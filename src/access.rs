@@ -0,0 +1,57 @@
+// Copyright 2020-2021, Cerno
+// Licensed under the MIT License
+// See the LICENSE file or <http://opensource.org/licenses/MIT>
+
+use crate::SyncMode;
+
+mod sealed {
+    pub trait Sealed {}
+}
+
+/// Access granted to the CPU by a [`Mapping`](crate::Mapping)
+///
+/// It's implemented by the [`Ro`], [`Rw`] and [`Wo`] markers, and can't be implemented outside of
+/// this crate.
+pub trait Access: sealed::Sealed {
+    /// Direction of the CPU access started when the buffer is mapped
+    const SYNC: SyncMode;
+}
+
+/// Access of the mappings the CPU can read, [`Ro`] and [`Rw`]
+///
+/// It can't be implemented outside of this crate either.
+pub trait Readable: Access {}
+
+/// Marker of the mappings the CPU can only read
+#[derive(Clone, Copy, Debug)]
+pub enum Ro {}
+
+impl sealed::Sealed for Ro {}
+
+impl Access for Ro {
+    const SYNC: SyncMode = SyncMode::Read;
+}
+
+impl Readable for Ro {}
+
+/// Marker of the mappings the CPU can read from and write to
+#[derive(Clone, Copy, Debug)]
+pub enum Rw {}
+
+impl sealed::Sealed for Rw {}
+
+impl Access for Rw {
+    const SYNC: SyncMode = SyncMode::ReadWrite;
+}
+
+impl Readable for Rw {}
+
+/// Marker of the mappings the CPU can only write to
+#[derive(Clone, Copy, Debug)]
+pub enum Wo {}
+
+impl sealed::Sealed for Wo {}
+
+impl Access for Wo {
+    const SYNC: SyncMode = SyncMode::Write;
+}
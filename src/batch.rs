@@ -0,0 +1,96 @@
+// Copyright 2020-2021, Cerno
+// Licensed under the MIT License
+// See the LICENSE file or <http://opensource.org/licenses/MIT>
+
+use alloc::boxed::Box;
+use core::mem::ManuallyDrop;
+
+use log::warn;
+
+use crate::{DmaBuf, MapError, SyncMode};
+
+/// A CPU access to several [`DmaBuf`] at once
+///
+/// Frame loops often need to access a set of buffers together. A `SyncBatch` starts the CPU access
+/// to all of them when it's created, and ends them all when it's released or dropped, so that the
+/// whole set shares a single, clear, lifetime.
+///
+/// The kernel doesn't provide a way to synchronize several buffers in a single call, so one
+/// `DMA_BUF_IOCTL_SYNC` ioctl is still issued per buffer.
+#[derive(Debug)]
+pub struct SyncBatch<'a> {
+    buffers: &'a [&'a DmaBuf],
+    mode: SyncMode,
+}
+
+impl<'a> SyncBatch<'a> {
+    /// Starts a CPU access to all the `buffers`, in order
+    ///
+    /// # Errors
+    ///
+    /// Will return [`MapError::Batch`] with the index of the first buffer whose CPU access
+    /// couldn't be started. The accesses to the buffers before it are ended before returning.
+    pub fn begin(buffers: &'a [&'a DmaBuf], mode: SyncMode) -> Result<Self, MapError> {
+        for (index, buf) in buffers.iter().enumerate() {
+            if let Err(e) = buf.begin_cpu_access(mode) {
+                for started in &buffers[..index] {
+                    if started.end_cpu_access(mode).is_err() {
+                        warn!("Ending the CPU access failed!");
+                    }
+                }
+
+                return Err(MapError::Batch {
+                    index,
+                    source: Box::new(e),
+                });
+            }
+        }
+
+        Ok(Self { buffers, mode })
+    }
+
+    /// Returns the buffers of the batch
+    #[must_use]
+    pub fn buffers(&self) -> &[&'a DmaBuf] {
+        self.buffers
+    }
+
+    fn end_all(&self) -> Result<(), MapError> {
+        let mut ret = Ok(());
+
+        for (index, buf) in self.buffers.iter().enumerate() {
+            if let Err(e) = buf.end_cpu_access(self.mode) {
+                if ret.is_ok() {
+                    ret = Err(MapError::Batch {
+                        index,
+                        source: Box::new(e),
+                    });
+                }
+            }
+        }
+
+        ret
+    }
+
+    /// Ends the CPU access to all the buffers
+    ///
+    /// Dropping the batch does the same, but can only log failures.
+    ///
+    /// # Errors
+    ///
+    /// Will return [`MapError::Batch`] with the index of the first buffer whose CPU access
+    /// couldn't be ended. The accesses to all the other buffers are ended nonetheless.
+    pub fn end(self) -> Result<(), MapError> {
+        let this = ManuallyDrop::new(self);
+
+        this.end_all()
+    }
+}
+
+impl Drop for SyncBatch<'_> {
+    fn drop(&mut self) {
+        if self.end_all().is_err() {
+            warn!("Ending the CPU access failed!");
+        }
+    }
+}
@@ -0,0 +1,75 @@
+// Copyright 2020-2021, Cerno
+// Licensed under the MIT License
+// See the LICENSE file or <http://opensource.org/licenses/MIT>
+
+use std::io;
+
+use crate::{seek_position, MappedDmaBuf};
+
+/// A reader over several [`MappedDmaBuf`] as if they were a single contiguous buffer
+///
+/// Some allocations are segmented across several buffers while their content is a single logical
+/// stream. A `ChainedReader` presents those buffers, in order, through [`io::Read`] and
+/// [`io::Seek`], and splits reads crossing a buffer boundary internally.
+///
+/// Every read issues the read cache synchronization of each buffer it touches.
+#[derive(Debug)]
+pub struct ChainedReader<'a> {
+    buffers: &'a [&'a MappedDmaBuf],
+    pos: usize,
+}
+
+impl<'a> ChainedReader<'a> {
+    /// Creates a new `ChainedReader` over `buffers`, positioned at the start of the first buffer
+    #[must_use]
+    pub fn new(buffers: &'a [&'a MappedDmaBuf]) -> Self {
+        Self { buffers, pos: 0 }
+    }
+
+    fn total_len(&self) -> usize {
+        self.buffers.iter().map(|mapping| mapping.len).sum()
+    }
+}
+
+impl io::Read for ChainedReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut read = 0;
+        let mut start = 0;
+
+        for mapping in self.buffers {
+            let end = start + mapping.len;
+
+            if self.pos < end && read < buf.len() {
+                let offset = self.pos - start;
+                let count = (end - self.pos).min(buf.len() - read);
+
+                mapping.read(
+                    |bytes, out: Option<&mut [u8]>| {
+                        if let Some(out) = out {
+                            out.copy_from_slice(&bytes[offset..offset + count]);
+                        }
+
+                        Ok(())
+                    },
+                    Some(&mut buf[read..read + count]),
+                )?;
+
+                read += count;
+                self.pos += count;
+            }
+
+            start = end;
+        }
+
+        Ok(read)
+    }
+}
+
+impl io::Seek for ChainedReader<'_> {
+    fn seek(&mut self, pos: io::SeekFrom) -> io::Result<u64> {
+        let new = seek_position(self.pos, self.total_len(), pos)?;
+        self.pos = usize::try_from(new).map_err(io::Error::other)?;
+
+        Ok(new)
+    }
+}
@@ -0,0 +1,79 @@
+// Copyright 2020-2021, Cerno
+// Licensed under the MIT License
+// See the LICENSE file or <http://opensource.org/licenses/MIT>
+
+use core::ops::{Deref, DerefMut};
+
+use crate::{DmaBuf, MapError, MappedDmaBufRw};
+
+/// A [`MappedDmaBufRw`] whose cache coherency can be checkpointed while it's mapped
+///
+/// The caches are normally synchronized when the CPU access starts, when the buffer is mapped,
+/// and when it ends, when the mapping is released or dropped. For long CPU sessions on a buffer
+/// shared with a device, [`CheckpointMapping::checkpoint`] synchronizes them in between, without
+/// remapping the buffer.
+///
+/// It dereferences to the [`MappedDmaBufRw`] to access the buffer.
+#[derive(Debug)]
+pub struct CheckpointMapping<'a>(MappedDmaBufRw<'a>);
+
+impl<'a> CheckpointMapping<'a> {
+    /// Maps `buf` for the CPU to read from and write to it
+    ///
+    /// # Errors
+    ///
+    /// Will return an error if mapping the buffer fails. See [`DmaBuf::memory_map_rw`].
+    pub fn new(buf: &'a mut DmaBuf) -> Result<Self, MapError> {
+        buf.memory_map_rw().map(Self)
+    }
+
+    /// Synchronizes the caches, without unmapping the buffer
+    ///
+    /// The CPU access is ended, which makes the CPU writes visible to the devices, and started
+    /// again, which makes the device writes visible to the CPU. This is the `DMA_BUF_IOCTL_SYNC`
+    /// equivalent of releasing the mapping and mapping the buffer again.
+    ///
+    /// # Errors
+    ///
+    /// Will return [`MapError::FdAccess`] if either ending or starting the CPU access fails. If
+    /// starting it again failed, the mapping is left without any CPU access, and shouldn't be
+    /// accessed anymore.
+    pub fn checkpoint(&mut self) -> Result<(), MapError> {
+        let mapping = &mut self.0;
+        if !mapping.sync {
+            return Ok(());
+        }
+
+        mapping.buf.end_cpu_access(mapping.access)?;
+
+        if let Err(e) = mapping.buf.begin_cpu_access(mapping.access) {
+            mapping.sync = false;
+            return Err(e);
+        }
+
+        Ok(())
+    }
+
+    /// Ends the CPU access, and unmaps the buffer
+    ///
+    /// # Errors
+    ///
+    /// See [`MappedDmaBufRw::release`].
+    pub fn release(self) -> Result<(), MapError> {
+        self.0.release()
+    }
+}
+
+impl<'a> Deref for CheckpointMapping<'a> {
+    type Target = MappedDmaBufRw<'a>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl DerefMut for CheckpointMapping<'_> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
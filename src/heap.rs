@@ -0,0 +1,135 @@
+// Copyright 2020-2021, Cerno
+// Licensed under the MIT License
+// See the LICENSE file or <http://opensource.org/licenses/MIT>
+
+use alloc::{borrow::ToOwned as _, format};
+#[cfg(feature = "std")]
+use alloc::{string::String, vec::Vec};
+
+use log::debug;
+#[cfg(feature = "std")]
+use rustix::io::Errno;
+use rustix::{
+    fd::{AsFd, AsRawFd, BorrowedFd, OwnedFd, RawFd},
+    fs::{open, Mode, OFlags},
+};
+
+use crate::{ioctl::dma_heap_alloc, DmaBuf, MapError};
+
+const DMA_HEAP_DIR: &str = "/dev/dma_heap";
+
+/// Flags passed to a [`DmaHeap`] when allocating a buffer
+///
+/// The kernel doesn't define any heap flag so far, and rejects any non-empty set of flags.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct HeapFlags(u64);
+
+impl HeapFlags {
+    /// Returns an empty set of flags
+    #[must_use]
+    pub const fn empty() -> Self {
+        Self(0)
+    }
+
+    /// Creates a set of flags from its raw value
+    #[must_use]
+    pub const fn from_bits_retain(bits: u64) -> Self {
+        Self(bits)
+    }
+
+    /// Returns the raw value of the flags
+    #[must_use]
+    pub const fn bits(self) -> u64 {
+        self.0
+    }
+}
+
+/// A DMA-Buf heap, to allocate buffers from
+///
+/// The kernel exposes the heaps it supports as character devices in `/dev/dma_heap`, such as
+/// `system` for buffers backed by regular pages, or `cma` for physically contiguous buffers.
+#[derive(Debug)]
+pub struct DmaHeap(OwnedFd);
+
+impl DmaHeap {
+    /// Returns the names of the heaps available on the system, sorted alphabetically
+    ///
+    /// # Errors
+    ///
+    /// Will return [`MapError::FdAccess`] if `/dev/dma_heap` can't be read, most likely with
+    /// `ENOENT` if the kernel doesn't support DMA-Buf heaps.
+    #[cfg(feature = "std")]
+    pub fn list() -> Result<Vec<String>, MapError> {
+        let io_error = |e: std::io::Error| MapError::FdAccess {
+            reason: e.to_string(),
+            errno: e.raw_os_error().map(Errno::from_raw_os_error),
+            source: e,
+        };
+
+        let mut heaps = std::fs::read_dir(DMA_HEAP_DIR)
+            .map_err(io_error)?
+            .map(|entry| entry.map(|entry| entry.file_name().to_string_lossy().into_owned()))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(io_error)?;
+
+        heaps.sort_unstable();
+
+        Ok(heaps)
+    }
+
+    /// Opens the heap called `name` in `/dev/dma_heap`
+    ///
+    /// # Errors
+    ///
+    /// Will return [`MapError::InvalidName`] if `name` is empty, `.`, `..` or contains a `/`, since
+    /// it wouldn't name a heap in `/dev/dma_heap`, or [`MapError::FdAccess`] if the heap can't be
+    /// opened, with `ENOENT` if it doesn't exist.
+    pub fn open(name: &str) -> Result<Self, MapError> {
+        if matches!(name, "" | "." | "..") || name.contains('/') {
+            return Err(MapError::InvalidName {
+                name: name.to_owned(),
+                reason: "the name isn't a file in /dev/dma_heap",
+            });
+        }
+
+        let path = format!("{DMA_HEAP_DIR}/{name}");
+        let fd = open(
+            path.as_str(),
+            OFlags::RDONLY | OFlags::CLOEXEC,
+            Mode::empty(),
+        )
+        .map_err(MapError::fd_access)?;
+
+        debug!("Opened DMA-Buf heap {path}, File Descriptor {fd:#?}");
+
+        Ok(Self(fd))
+    }
+
+    /// Allocates a new buffer of `len` bytes from the heap
+    ///
+    /// The buffer file descriptor is opened for reading and writing.
+    ///
+    /// # Errors
+    ///
+    /// Will return [`MapError::FdAccess`] if the allocation fails, or
+    /// [`MapError::IntegerConversionFailed`] if `len` doesn't fit in the kernel structure.
+    pub fn alloc(&self, len: usize, flags: HeapFlags) -> Result<DmaBuf, MapError> {
+        let fd = dma_heap_alloc(self.0.as_fd(), len, flags.bits())?;
+
+        debug!("Allocated {len} bytes DMA-Buf, File Descriptor {fd:#?}");
+
+        Ok(DmaBuf::from(fd))
+    }
+}
+
+impl AsFd for DmaHeap {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.0.as_fd()
+    }
+}
+
+impl AsRawFd for DmaHeap {
+    fn as_raw_fd(&self) -> RawFd {
+        self.0.as_raw_fd()
+    }
+}
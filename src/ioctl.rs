@@ -1,19 +1,36 @@
-use std::os::fd::BorrowedFd;
+use alloc::{borrow::ToOwned, ffi::CString};
 
 use rustix::{
+    fd::{AsRawFd, BorrowedFd, FromRawFd, OwnedFd},
+    fs::OFlags,
     io::Errno,
-    ioctl::{ioctl, Setter, WriteOpcode},
+    ioctl::{ioctl, IntegerSetter, ReadWriteOpcode, Setter, Updater, WriteOpcode},
 };
 
-use crate::BufferError;
+use crate::MapError;
+
+pub(crate) const DMA_BUF_MAGIC: u32 = 0x444d_4142;
 
 const DMA_BUF_BASE: u8 = b'b';
 const DMA_BUF_IOCTL_SYNC: u8 = 0;
+const DMA_BUF_SET_NAME_B: u8 = 1;
+const DMA_BUF_IOCTL_EXPORT_SYNC_FILE: u8 = 2;
+const DMA_BUF_IOCTL_IMPORT_SYNC_FILE: u8 = 3;
+
+const DMA_BUF_NAME_LEN: usize = 32;
+
+const DMA_BUF_SYNC_READ: u32 = 1 << 0;
+const DMA_BUF_SYNC_WRITE: u32 = 1 << 1;
+const DMA_BUF_SYNC_START: u32 = 0 << 2;
+const DMA_BUF_SYNC_END: u32 = 1 << 2;
+
+const DMA_HEAP_IOC_MAGIC: u8 = b'H';
+const DMA_HEAP_IOCTL_ALLOC: u8 = 0;
 
-const DMA_BUF_SYNC_READ: u64 = 1 << 0;
-const DMA_BUF_SYNC_WRITE: u64 = 1 << 1;
-const DMA_BUF_SYNC_START: u64 = 0 << 2;
-const DMA_BUF_SYNC_END: u64 = 1 << 2;
+const SYNC_IOC_MAGIC: u8 = b'>';
+const SYNC_IOC_MERGE: u8 = 3;
+
+const SYNC_MERGE_NAME: &[u8] = b"dma-buf merged fence";
 
 #[derive(Default)]
 #[repr(C)]
@@ -21,54 +38,281 @@ struct dma_buf_sync {
     flags: u64,
 }
 
-fn dma_buf_sync_ioctl(fd: BorrowedFd<'_>, flags: u64) -> Result<(), Errno> {
-    type Opcode = WriteOpcode<DMA_BUF_BASE, DMA_BUF_IOCTL_SYNC, dma_buf_sync>;
+#[repr(C)]
+struct dma_buf_export_sync_file {
+    flags: u32,
+    fd: i32,
+}
 
-    let sync = dma_buf_sync { flags };
+#[repr(C)]
+struct dma_buf_import_sync_file {
+    flags: u32,
+    fd: i32,
+}
 
-    // SAFETY: This function is unsafe because the opcode has to be valid, and the value type must
-    // match. We have checked those, so we're good.
-    let ioctl_type = unsafe { Setter::<Opcode, dma_buf_sync>::new(sync) };
+#[repr(C)]
+struct dma_heap_allocation_data {
+    len: u64,
+    fd: u32,
+    fd_flags: u32,
+    heap_flags: u64,
+}
 
-    // SAFETY: This function is unsafe because the driver isn't guaranteed to implement the ioctl,
-    // and to implement it properly. We don't have much of a choice and still have to trust the
-    // kernel there.
-    unsafe { ioctl(fd, ioctl_type) }
+#[repr(C)]
+struct sync_merge_data {
+    name: [u8; 32],
+    fd2: i32,
+    fence: i32,
+    flags: u32,
+    pad: u32,
 }
 
-fn dma_buf_sync(fd: BorrowedFd<'_>, flags: u64) -> Result<(), BufferError> {
-    dma_buf_sync_ioctl(fd, flags).map_err(|e| BufferError::FdAccess {
-        reason: e.to_string(),
-        source: std::io::Error::from(e),
+/// Calls `f` until it returns something else than `EINTR`
+pub(crate) fn retry_on_eintr<T>(mut f: impl FnMut() -> Result<T, Errno>) -> Result<T, Errno> {
+    loop {
+        let res = f();
+        if !matches!(res, Err(Errno::INTR)) {
+            return res;
+        }
+    }
+}
+
+fn dma_buf_sync_ioctl(fd: BorrowedFd<'_>, flags: u32) -> Result<(), Errno> {
+    type Opcode = WriteOpcode<DMA_BUF_BASE, DMA_BUF_IOCTL_SYNC, dma_buf_sync>;
+
+    // The kernel documentation explicitly asks userspace to restart the ioctl if it gets
+    // interrupted by a signal.
+    retry_on_eintr(|| {
+        let sync = dma_buf_sync {
+            flags: u64::from(flags),
+        };
+
+        // SAFETY: This function is unsafe because the opcode has to be valid, and the value type
+        // must match. We have checked those, so we're good.
+        let ioctl_type = unsafe { Setter::<Opcode, dma_buf_sync>::new(sync) };
+
+        // SAFETY: This function is unsafe because the driver isn't guaranteed to implement the
+        // ioctl, and to implement it properly. We don't have much of a choice and still have to
+        // trust the kernel there.
+        unsafe { ioctl(fd, ioctl_type) }
     })
 }
 
-pub(crate) fn dma_buf_begin_cpu_read_access(fd: BorrowedFd<'_>) -> Result<(), BufferError> {
+fn ioctl_error(operation: &'static str, e: Errno) -> MapError {
+    if e == Errno::NOTTY || e == Errno::NOSYS {
+        MapError::Unsupported { operation }
+    } else if e == Errno::BUSY {
+        MapError::Busy
+    } else {
+        MapError::fd_access(e)
+    }
+}
+
+fn dma_buf_sync(fd: BorrowedFd<'_>, flags: u32) -> Result<(), MapError> {
+    dma_buf_sync_ioctl(fd, flags).map_err(|e| ioctl_error("DMA_BUF_IOCTL_SYNC", e))
+}
+
+pub(crate) fn dma_buf_begin_cpu_read_access(fd: BorrowedFd<'_>) -> Result<(), MapError> {
     dma_buf_sync(fd, DMA_BUF_SYNC_START | DMA_BUF_SYNC_READ)
 }
 
-pub(crate) fn dma_buf_begin_cpu_readwrite_access(fd: BorrowedFd<'_>) -> Result<(), BufferError> {
+pub(crate) fn dma_buf_begin_cpu_readwrite_access(fd: BorrowedFd<'_>) -> Result<(), MapError> {
     dma_buf_sync(
         fd,
         DMA_BUF_SYNC_START | DMA_BUF_SYNC_WRITE | DMA_BUF_SYNC_READ,
     )
 }
 
-pub(crate) fn dma_buf_begin_cpu_write_access(fd: BorrowedFd<'_>) -> Result<(), BufferError> {
+pub(crate) fn dma_buf_begin_cpu_write_access(fd: BorrowedFd<'_>) -> Result<(), MapError> {
     dma_buf_sync(fd, DMA_BUF_SYNC_START | DMA_BUF_SYNC_WRITE)
 }
 
-pub(crate) fn dma_buf_end_cpu_read_access(fd: BorrowedFd<'_>) -> Result<(), BufferError> {
+pub(crate) fn dma_buf_end_cpu_read_access(fd: BorrowedFd<'_>) -> Result<(), MapError> {
     dma_buf_sync(fd, DMA_BUF_SYNC_END | DMA_BUF_SYNC_READ)
 }
 
-pub(crate) fn dma_buf_end_cpu_readwrite_access(fd: BorrowedFd<'_>) -> Result<(), BufferError> {
+pub(crate) fn dma_buf_end_cpu_readwrite_access(fd: BorrowedFd<'_>) -> Result<(), MapError> {
     dma_buf_sync(
         fd,
         DMA_BUF_SYNC_END | DMA_BUF_SYNC_WRITE | DMA_BUF_SYNC_READ,
     )
 }
 
-pub(crate) fn dma_buf_end_cpu_write_access(fd: BorrowedFd<'_>) -> Result<(), BufferError> {
+pub(crate) fn dma_buf_end_cpu_write_access(fd: BorrowedFd<'_>) -> Result<(), MapError> {
     dma_buf_sync(fd, DMA_BUF_SYNC_END | DMA_BUF_SYNC_WRITE)
 }
+
+fn sync_file_flags(read: bool, write: bool) -> u32 {
+    let mut flags = 0;
+
+    if read {
+        flags |= DMA_BUF_SYNC_READ;
+    }
+
+    if write {
+        flags |= DMA_BUF_SYNC_WRITE;
+    }
+
+    flags
+}
+
+fn dma_buf_export_sync_file_ioctl(fd: BorrowedFd<'_>, flags: u32) -> Result<OwnedFd, Errno> {
+    type Opcode =
+        ReadWriteOpcode<DMA_BUF_BASE, DMA_BUF_IOCTL_EXPORT_SYNC_FILE, dma_buf_export_sync_file>;
+
+    let mut data = dma_buf_export_sync_file { flags, fd: -1 };
+
+    // SAFETY: This function is unsafe because the opcode has to be valid, and the value type must
+    // match. We have checked those, so we're good.
+    let ioctl_type = unsafe { Updater::<Opcode, dma_buf_export_sync_file>::new(&mut data) };
+
+    // SAFETY: This function is unsafe because the driver isn't guaranteed to implement the ioctl,
+    // and to implement it properly. We don't have much of a choice and still have to trust the
+    // kernel there.
+    unsafe { ioctl(fd, ioctl_type) }?;
+
+    // SAFETY: The ioctl succeeded, so the kernel has created a new file descriptor for the
+    // sync_file, and we're its sole owner.
+    Ok(unsafe { OwnedFd::from_raw_fd(data.fd) })
+}
+
+pub(crate) fn dma_buf_export_sync_file(
+    fd: BorrowedFd<'_>,
+    read: bool,
+    write: bool,
+) -> Result<OwnedFd, MapError> {
+    dma_buf_export_sync_file_ioctl(fd, sync_file_flags(read, write))
+        .map_err(|e| ioctl_error("DMA_BUF_IOCTL_EXPORT_SYNC_FILE", e))
+}
+
+fn dma_buf_import_sync_file_ioctl(
+    fd: BorrowedFd<'_>,
+    fence: BorrowedFd<'_>,
+    flags: u32,
+) -> Result<(), Errno> {
+    type Opcode =
+        WriteOpcode<DMA_BUF_BASE, DMA_BUF_IOCTL_IMPORT_SYNC_FILE, dma_buf_import_sync_file>;
+
+    let import = dma_buf_import_sync_file {
+        flags,
+        fd: fence.as_raw_fd(),
+    };
+
+    // SAFETY: This function is unsafe because the opcode has to be valid, and the value type must
+    // match. We have checked those, so we're good.
+    let ioctl_type = unsafe { Setter::<Opcode, dma_buf_import_sync_file>::new(import) };
+
+    // SAFETY: This function is unsafe because the driver isn't guaranteed to implement the ioctl,
+    // and to implement it properly. We don't have much of a choice and still have to trust the
+    // kernel there.
+    unsafe { ioctl(fd, ioctl_type) }
+}
+
+pub(crate) fn dma_buf_import_sync_file(
+    fd: BorrowedFd<'_>,
+    fence: BorrowedFd<'_>,
+    read: bool,
+    write: bool,
+) -> Result<(), MapError> {
+    dma_buf_import_sync_file_ioctl(fd, fence, sync_file_flags(read, write))
+        .map_err(|e| ioctl_error("DMA_BUF_IOCTL_IMPORT_SYNC_FILE", e))
+}
+
+fn dma_buf_set_name_ioctl(fd: BorrowedFd<'_>, name: &CString) -> Result<(), Errno> {
+    type Opcode = WriteOpcode<DMA_BUF_BASE, DMA_BUF_SET_NAME_B, u64>;
+
+    // SAFETY: This function is unsafe because the opcode has to be valid, and the value must match
+    // what the kernel expects. The kernel expects a pointer to a NUL-terminated string, which
+    // our CString provides and which outlives the ioctl call.
+    let ioctl_type = unsafe { IntegerSetter::<Opcode>::new(name.as_ptr().expose_provenance()) };
+
+    // SAFETY: This function is unsafe because the driver isn't guaranteed to implement the ioctl,
+    // and to implement it properly. We don't have much of a choice and still have to trust the
+    // kernel there.
+    unsafe { ioctl(fd, ioctl_type) }
+}
+
+pub(crate) fn dma_buf_set_name(fd: BorrowedFd<'_>, name: &str) -> Result<(), MapError> {
+    if name.len() >= DMA_BUF_NAME_LEN {
+        return Err(MapError::InvalidName {
+            name: name.to_owned(),
+            reason: "the name is longer than 31 bytes",
+        });
+    }
+
+    let cname = CString::new(name).map_err(|_e| MapError::InvalidName {
+        name: name.to_owned(),
+        reason: "the name contains a NUL byte",
+    })?;
+
+    dma_buf_set_name_ioctl(fd, &cname).map_err(|e| ioctl_error("DMA_BUF_SET_NAME", e))
+}
+
+fn sync_merge_ioctl(fd: BorrowedFd<'_>, other: BorrowedFd<'_>) -> Result<OwnedFd, Errno> {
+    type Opcode = ReadWriteOpcode<SYNC_IOC_MAGIC, SYNC_IOC_MERGE, sync_merge_data>;
+
+    let mut data = sync_merge_data {
+        name: [0; 32],
+        fd2: other.as_raw_fd(),
+        fence: -1,
+        flags: 0,
+        pad: 0,
+    };
+    data.name[..SYNC_MERGE_NAME.len()].copy_from_slice(SYNC_MERGE_NAME);
+
+    // SAFETY: This function is unsafe because the opcode has to be valid, and the value type must
+    // match. We have checked those, so we're good.
+    let ioctl_type = unsafe { Updater::<Opcode, sync_merge_data>::new(&mut data) };
+
+    // SAFETY: This function is unsafe because the driver isn't guaranteed to implement the ioctl,
+    // and to implement it properly. We don't have much of a choice and still have to trust the
+    // kernel there.
+    unsafe { ioctl(fd, ioctl_type) }?;
+
+    // SAFETY: The ioctl succeeded, so the kernel has created a new file descriptor for the merged
+    // fence, and we're its sole owner.
+    Ok(unsafe { OwnedFd::from_raw_fd(data.fence) })
+}
+
+pub(crate) fn sync_file_merge(
+    fd: BorrowedFd<'_>,
+    other: BorrowedFd<'_>,
+) -> Result<OwnedFd, MapError> {
+    sync_merge_ioctl(fd, other).map_err(|e| ioctl_error("SYNC_IOC_MERGE", e))
+}
+
+fn dma_heap_alloc_ioctl(fd: BorrowedFd<'_>, len: u64, heap_flags: u64) -> Result<OwnedFd, Errno> {
+    type Opcode =
+        ReadWriteOpcode<DMA_HEAP_IOC_MAGIC, DMA_HEAP_IOCTL_ALLOC, dma_heap_allocation_data>;
+
+    let mut data = dma_heap_allocation_data {
+        len,
+        fd: 0,
+        fd_flags: (OFlags::RDWR | OFlags::CLOEXEC).bits(),
+        heap_flags,
+    };
+
+    // SAFETY: This function is unsafe because the opcode has to be valid, and the value type must
+    // match. We have checked those, so we're good.
+    let ioctl_type = unsafe { Updater::<Opcode, dma_heap_allocation_data>::new(&mut data) };
+
+    // SAFETY: This function is unsafe because the driver isn't guaranteed to implement the ioctl,
+    // and to implement it properly. We don't have much of a choice and still have to trust the
+    // kernel there.
+    unsafe { ioctl(fd, ioctl_type) }?;
+
+    let raw = i32::try_from(data.fd).map_err(|_e| Errno::BADF)?;
+
+    // SAFETY: The ioctl succeeded, so the kernel has created a new file descriptor for the
+    // buffer, and we're its sole owner.
+    Ok(unsafe { OwnedFd::from_raw_fd(raw) })
+}
+
+pub(crate) fn dma_heap_alloc(
+    fd: BorrowedFd<'_>,
+    len: usize,
+    heap_flags: u64,
+) -> Result<OwnedFd, MapError> {
+    dma_heap_alloc_ioctl(fd, u64::try_from(len)?, heap_flags)
+        .map_err(|e| ioctl_error("DMA_HEAP_IOCTL_ALLOC", e))
+}
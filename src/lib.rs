@@ -20,290 +20,2497 @@
         clippy::empty_enum_variants_with_brackets
     )
 )]
+#![cfg_attr(not(feature = "std"), no_std)]
 #![doc = include_str!("../README.md")]
 
-use core::{ffi::c_void, fmt, num::TryFromIntError, ptr, slice};
-use std::os::fd::{AsFd, AsRawFd, BorrowedFd, FromRawFd, OwnedFd, RawFd};
+extern crate alloc;
+
+// The tokio development dependency is only used by the tests of the tokio feature.
+#[cfg(all(test, not(feature = "tokio")))]
+use tokio as _;
+
+use alloc::{
+    boxed::Box,
+    string::{String, ToString},
+    vec::Vec,
+};
+use core::{
+    ffi::c_void,
+    fmt,
+    marker::PhantomData,
+    mem::{ManuallyDrop, MaybeUninit},
+    num::TryFromIntError,
+    ops::{Deref, DerefMut, Index, IndexMut},
+    ptr,
+    slice::{self, SliceIndex},
+    sync::atomic::{AtomicU8, AtomicUsize, Ordering},
+    time::Duration,
+};
 
 use log::{debug, warn};
 use rustix::{
-    fs::fstat,
-    mm::{mmap, munmap, MapFlags, ProtFlags},
+    event::{
+        epoll::{self, EventData, EventFlags},
+        poll, PollFd, PollFlags,
+    },
+    fd::{AsFd, AsRawFd, BorrowedFd, FromRawFd, IntoRawFd, OwnedFd, RawFd},
+    fs::{fcntl_getfl, fstat, fstatfs, OFlags},
+    io::{dup, fcntl_dupfd_cloexec, write, Errno},
+    mm::{madvise, mlock, mmap, msync, munlock, munmap, MapFlags, MsyncFlags, ProtFlags},
     param::page_size,
+    pipe::{splice, SpliceFlags},
+    time::{clock_gettime, ClockId},
 };
 
 mod ioctl;
 use ioctl::{
     dma_buf_begin_cpu_read_access, dma_buf_begin_cpu_readwrite_access,
     dma_buf_begin_cpu_write_access, dma_buf_end_cpu_read_access, dma_buf_end_cpu_readwrite_access,
-    dma_buf_end_cpu_write_access,
+    dma_buf_end_cpu_write_access, dma_buf_export_sync_file, dma_buf_import_sync_file,
+    dma_buf_set_name, retry_on_eintr, DMA_BUF_MAGIC,
 };
 
-/// Error type to map a [`DmaBuf`]
+mod access;
+pub use access::{Access, Readable, Ro, Rw, Wo};
+
+mod batch;
+pub use batch::SyncBatch;
+
+#[cfg(feature = "std")]
+mod chained;
+#[cfg(feature = "std")]
+pub use chained::ChainedReader;
+
+mod checkpoint;
+pub use checkpoint::CheckpointMapping;
+
+mod heap;
+pub use heap::{DmaHeap, HeapFlags};
+
+#[cfg(feature = "memmap2")]
+mod memmap;
+#[cfg(feature = "memmap2")]
+pub use memmap::{MemmapRo, MemmapRw};
+
+mod options;
+pub use options::MapOptions;
+
+mod shared;
+pub use shared::SharedMapping;
+
+mod swapchain;
+pub use swapchain::SwapChain;
+
+mod sync_file;
+pub use sync_file::SyncFile;
+
+mod window;
+pub use window::WindowedMapping;
+
+/// Number of times a mapping is attempted again when mmap fails with `EAGAIN`, by default
+const MMAP_RETRIES: u32 = 3;
+
+/// Size of the buffer the data goes through in [`DmaBuf::fill_from_reader`]
+#[cfg(feature = "std")]
+const FILL_CHUNK_LEN: usize = 4096;
+
+/// Time to wait for between two mmap attempts
+#[cfg(feature = "std")]
+const MMAP_RETRY_BACKOFF: Duration = Duration::from_micros(100);
+
+/// Error type to map and access a [`DmaBuf`]
+///
+/// The variants reporting a system call failure carry the error number the kernel returned, which
+/// allows to match on the exact failure, for example to fall back to a read-only mapping on
+/// `EPERM`. Their `source` field only exists when the `std` feature is enabled, so they are
+/// non-exhaustive and can only be matched with `..`.
 #[non_exhaustive]
 #[derive(thiserror::Error, Debug)]
 pub enum MapError {
     /// An Error occurred while accessing the buffer file descriptor
     #[error("Could not access the buffer file descriptor: {reason}")]
+    #[non_exhaustive]
     FdAccess {
         /// Description of the Error
         reason: String,
 
         /// Source of the Error
+        #[cfg(feature = "std")]
         source: std::io::Error,
+
+        /// Error number reported by the kernel, if any
+        errno: Option<Errno>,
     },
 
     /// An Error occurred while mapping the buffer file descriptor
     #[error("Could not map the buffer file descriptor: {reason}")]
+    #[non_exhaustive]
     MappingFailed {
         /// Description of the Error
         reason: String,
 
         /// Source of the Error
+        #[cfg(feature = "std")]
+        source: std::io::Error,
+
+        /// Error number reported by the kernel, if any
+        errno: Option<Errno>,
+    },
+
+    /// The kernel ran out of memory while mapping the buffer
+    ///
+    /// In containers, this is usually caused by the cgroup memory limit (`memory.max`) being
+    /// reached rather than by the system running out of memory.
+    #[error("Not enough memory to map the buffer (possibly a cgroup memory limit): {reason}")]
+    #[non_exhaustive]
+    OutOfMemory {
+        /// Description of the Error
+        reason: String,
+
+        /// Source of the Error
+        #[cfg(feature = "std")]
         source: std::io::Error,
+
+        /// Error number reported by the kernel, if any
+        errno: Option<Errno>,
+    },
+
+    /// The buffer size isn't a multiple of the required alignment
+    #[error("Buffer size {size} isn't a multiple of {alignment}")]
+    UnalignedSize {
+        /// Size of the buffer, in bytes
+        size: usize,
+
+        /// Required alignment, in bytes
+        alignment: usize,
+    },
+
+    /// The buffer is too large to be mapped in the address space
+    #[error("Buffer size {size} is too large to be mapped")]
+    TooLarge {
+        /// Size of the buffer, or of the mapping, in bytes
+        size: u64,
+    },
+
+    /// The file descriptor isn't a DMA-Buf
+    #[error("The file descriptor isn't a DMA-Buf")]
+    NotDmaBuf,
+
+    /// The buffer, or the range of it to map, is empty, and can't be mapped
+    ///
+    /// The kernel rejects mappings of 0 bytes, so empty buffers and ranges are reported as such
+    /// rather than through a failed mmap call.
+    #[error("The buffer is empty")]
+    EmptyBuffer,
+
+    /// The mapping address isn't a multiple of the required alignment
+    #[error("Address {address:#x} isn't a multiple of {alignment}")]
+    UnalignedAddress {
+        /// Requested address
+        address: usize,
+
+        /// Required alignment, in bytes
+        alignment: usize,
+    },
+
+    /// The requested range doesn't fit in the buffer
+    #[error("Range of {len} bytes at offset {offset} is out of the {bound} bytes bounds")]
+    RangeOutOfBounds {
+        /// Start of the requested range, in bytes
+        offset: usize,
+
+        /// Length of the requested range, in bytes
+        len: usize,
+
+        /// Size the range must fit in, in bytes
+        bound: usize,
     },
 
     /// An Error occurred while converting between Integer types
     #[error("Integer Conversion Error")]
     IntegerConversionFailed(#[from] TryFromIntError),
+
+    /// An Error occured in the closure
+    #[error("The closure returned an error: {0}")]
+    Closure(Box<dyn core::error::Error + Send + Sync>),
+
+    /// The kernel doesn't support the requested operation
+    #[error("The {operation} operation isn't supported by the kernel")]
+    Unsupported {
+        /// Name of the unsupported operation
+        operation: &'static str,
+    },
+
+    /// The buffer content can't be viewed as a slice of the requested type
+    #[error("Can't view the buffer as a slice of {type_name}: {reason}")]
+    InvalidCast {
+        /// Name of the requested type
+        type_name: &'static str,
+
+        /// Why the cast failed
+        reason: &'static str,
+    },
+
+    /// The buffer or heap name can't be passed to the kernel
+    #[error("Invalid name {name:?}: {reason}")]
+    InvalidName {
+        /// Rejected name
+        name: String,
+
+        /// Why the name was rejected
+        reason: &'static str,
+    },
+
+    /// The operation failed on one of the buffers of a [`SyncBatch`]
+    #[error("Buffer {index} of the batch failed: {source}")]
+    Batch {
+        /// Index of the buffer that failed in the batch
+        index: usize,
+
+        /// Error that occurred on that buffer
+        source: Box<MapError>,
+    },
+
+    /// The buffer is still in use by a device
+    #[error("The buffer is busy")]
+    Busy,
+
+    /// The buffer fences didn't signal before the timeout expired
+    #[error("The buffer wasn't ready after {0:?}")]
+    Timeout(Duration),
+
+    /// An Error occurred while reading from or writing to a stream
+    #[cfg(feature = "std")]
+    #[error("Stream access failed: {0}")]
+    Io(std::io::Error),
+}
+
+impl MapError {
+    fn fd_access(e: Errno) -> Self {
+        Self::FdAccess {
+            reason: e.to_string(),
+            #[cfg(feature = "std")]
+            source: std::io::Error::from(e),
+            errno: Some(e),
+        }
+    }
+
+    fn mapping_failed(e: Errno) -> Self {
+        Self::MappingFailed {
+            reason: e.to_string(),
+            #[cfg(feature = "std")]
+            source: std::io::Error::from(e),
+            errno: Some(e),
+        }
+    }
+
+    fn out_of_memory(e: Errno) -> Self {
+        Self::OutOfMemory {
+            reason: e.to_string(),
+            #[cfg(feature = "std")]
+            source: std::io::Error::from(e),
+            errno: Some(e),
+        }
+    }
+}
+
+/// Direction of a CPU access to a [`DmaBuf`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SyncMode {
+    /// The CPU will only read from the buffer
+    Read,
+
+    /// The CPU will only write to the buffer
+    Write,
+
+    /// The CPU will both read from and write to the buffer
+    ReadWrite,
+}
+
+/// Access mode of a [`DmaBuf`] file descriptor
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AccessMode {
+    /// The file descriptor only allows reading
+    ReadOnly,
+
+    /// The file descriptor only allows writing, and thus can't be mapped
+    WriteOnly,
+
+    /// The file descriptor allows both reading and writing
+    ReadWrite,
+}
+
+/// Expected access pattern to a mapped [`DmaBuf`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Advice {
+    /// The mapping will be accessed sequentially
+    Sequential,
+
+    /// The mapping will be accessed in a random order
+    Random,
+
+    /// The mapping will be accessed soon, and can be prefetched
+    WillNeed,
+
+    /// The mapping won't be accessed soon
+    ///
+    /// The pages content isn't lost, since the mapping is shared, but accessing them later will
+    /// fault them in again.
+    DontNeed,
+}
+
+impl From<Advice> for rustix::mm::Advice {
+    fn from(advice: Advice) -> Self {
+        match advice {
+            Advice::Sequential => Self::Sequential,
+            Advice::Random => Self::Random,
+            Advice::WillNeed => Self::WillNeed,
+            Advice::DontNeed => Self::LinuxDontNeed,
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+fn seek_position(current: usize, len: usize, pos: std::io::SeekFrom) -> std::io::Result<u64> {
+    let current = u64::try_from(current).map_err(std::io::Error::other)?;
+    let len = u64::try_from(len).map_err(std::io::Error::other)?;
+
+    match pos {
+        std::io::SeekFrom::Start(offset) => Some(offset),
+        std::io::SeekFrom::End(delta) => len.checked_add_signed(delta),
+        std::io::SeekFrom::Current(delta) => current.checked_add_signed(delta),
+    }
+    .ok_or_else(|| {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "invalid seek to a negative or overflowing position",
+        )
+    })
+}
+
+fn copy_from_offset(src: &[u8], offset: usize, buf: &mut [u8]) -> Result<usize, MapError> {
+    let start = checked_subrange(offset, 0, src.len())?.start;
+    let src = &src[start..];
+
+    let count = buf.len().min(src.len());
+    buf[..count].copy_from_slice(&src[..count]);
+
+    Ok(count)
+}
+
+/// Copies as much of `src` as fits at the start of `dst`, and returns the number of bytes copied
+fn copy_to_uninit(dst: &mut [MaybeUninit<u8>], src: &[u8]) -> usize {
+    for (dst, src) in dst.iter_mut().zip(src) {
+        dst.write(*src);
+    }
+
+    dst.len().min(src.len())
+}
+
+fn copy_to_offset(dst: &mut [u8], offset: usize, data: &[u8]) -> Result<usize, MapError> {
+    let start = checked_subrange(offset, 0, dst.len())?.start;
+    let dst = &mut dst[start..];
+
+    let count = data.len().min(dst.len());
+    dst[..count].copy_from_slice(&data[..count]);
+
+    Ok(count)
+}
+
+/// Returns the `len` bytes long range starting at `offset`, if it fits within `bound` bytes
+///
+/// All the functions taking a range or an offset in a buffer or a mapping check it through there,
+/// so that `offset + len` can't overflow, and the error is the same everywhere. Offsets are
+/// checked as an empty range, or a single byte one if there must be data at the offset.
+fn checked_subrange(
+    offset: usize,
+    len: usize,
+    bound: usize,
+) -> Result<core::ops::Range<usize>, MapError> {
+    offset
+        .checked_add(len)
+        .filter(|end| *end <= bound)
+        .map(|end| offset..end)
+        .ok_or(MapError::RangeOutOfBounds { offset, len, bound })
+}
+
+#[cfg(feature = "bytemuck")]
+fn cast_error<T>(e: bytemuck::PodCastError) -> MapError {
+    let reason = match e {
+        bytemuck::PodCastError::TargetAlignmentGreaterAndInputNotAligned
+        | bytemuck::PodCastError::AlignmentMismatch => "the mapping isn't suitably aligned",
+        bytemuck::PodCastError::OutputSliceWouldHaveSlop | bytemuck::PodCastError::SizeMismatch => {
+            "the mapping length isn't a multiple of the type size"
+        }
+    };
+
+    MapError::InvalidCast {
+        type_name: core::any::type_name::<T>(),
+        reason,
+    }
+}
+
+/// Layout of the mapping of a part of a buffer
+#[derive(Clone, Copy, Debug)]
+struct Layout {
+    /// Offset in the buffer the mapped region starts at, aligned down to the page size
+    offset: usize,
+
+    /// Offset in the mapped region of the first byte requested
+    delta: usize,
+
+    /// Number of bytes requested
+    len: usize,
+
+    /// Length of the mapped region, rounded up to the page size
+    mapped_len: usize,
+}
+
+/// Validates a mapping of `len` bytes at `offset` in a buffer of `size` bytes
+///
+/// The mapping is placed at `address`, which must be aligned to `page`, the page size, which is a
+/// parameter so that the computations don't depend on the machine. `offset` doesn't need to be
+/// aligned: the mapped region starts on the page `offset` is in. If `len` is `None`, the mapping
+/// covers the rest of the buffer past `offset`.
+fn mapping_layout(
+    size: usize,
+    offset: usize,
+    address: usize,
+    len: Option<usize>,
+    page: usize,
+) -> Result<Layout, MapError> {
+    if size == 0 {
+        return Err(MapError::EmptyBuffer);
+    }
+
+    if !address.is_multiple_of(page) {
+        return Err(MapError::UnalignedAddress {
+            address,
+            alignment: page,
+        });
+    }
+
+    let len = len.unwrap_or(size.saturating_sub(offset));
+    checked_subrange(offset, len, size)?;
+    if len == 0 {
+        return Err(MapError::EmptyBuffer);
+    }
+
+    let delta = offset % page;
+    let Some(mapped_len) = (delta + len).checked_next_multiple_of(page) else {
+        return Err(MapError::TooLarge {
+            size: u64::try_from(len)?,
+        });
+    };
+    debug!("Valid buffer, size {size}, mapping {mapped_len} bytes at offset {offset}");
+
+    Ok(Layout {
+        offset: offset - delta,
+        delta,
+        len,
+        mapped_len,
+    })
+}
+
+/// Returns the offsets of one byte in each page spanned by a mapping of `len` bytes
+///
+/// The mapping starts `delta` bytes past the start of its first page.
+fn page_offsets(delta: usize, len: usize, page: usize) -> impl Iterator<Item = usize> {
+    let next_page = page - delta % page;
+
+    (len > 0)
+        .then_some(0)
+        .into_iter()
+        .chain((next_page..len).step_by(page))
+}
+
+fn poll_fd(
+    fd: BorrowedFd<'_>,
+    events: PollFlags,
+    timeout: Option<Duration>,
+) -> Result<bool, MapError> {
+    let mut fds = [PollFd::new(&fd, events)];
+    let deadline = timeout.and_then(|timeout| monotonic_now().checked_add(timeout));
+
+    // poll() can be interrupted by a signal, in which case we start over with whatever time is
+    // left before the deadline.
+    let ready = retry_on_eintr(|| {
+        let timeout_ms = deadline.map_or(-1, |deadline| {
+            poll_timeout_ms(deadline.saturating_sub(monotonic_now()))
+        });
+
+        poll(&mut fds, timeout_ms)
+    })
+    .map_err(MapError::fd_access)?;
+
+    Ok(ready != 0)
+}
+
+fn monotonic_now() -> Duration {
+    let now = clock_gettime(ClockId::Monotonic);
+
+    // The monotonic clock can't be negative.
+    Duration::new(
+        u64::try_from(now.tv_sec).unwrap_or(0),
+        u32::try_from(now.tv_nsec).unwrap_or(0),
+    )
+}
+
+/// Converts `timeout` to milliseconds for `poll()`, rounding up so that a timeout shorter than a
+/// millisecond still waits
+fn poll_timeout_ms(timeout: Duration) -> i32 {
+    i32::try_from(timeout.as_nanos().div_ceil(1_000_000)).unwrap_or(i32::MAX)
+}
+
+/// Statistics exposed by the kernel about a [`DmaBuf`]
+#[cfg(feature = "std")]
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct DmaBufStats {
+    /// Size of the buffer, in bytes
+    pub size: usize,
+
+    /// Name of the driver that exported the buffer
+    pub exporter_name: String,
 }
 
 /// A DMA-Buf buffer
 #[derive(Debug)]
-pub struct DmaBuf(OwnedFd);
+pub struct DmaBuf {
+    fd: OwnedFd,
+    // Size of the buffer, or 0 if it hasn't been retrieved yet. The kernel doesn't allow empty
+    // buffers, so there's no ambiguity.
+    len: AtomicUsize,
+}
+
+impl DmaBuf {
+    /// Prepares the buffer for a CPU access, waiting at most `timeout` for the devices to be done
+    ///
+    /// The `DMA_BUF_IOCTL_SYNC` ioctl will block until the fences attached to the buffer are
+    /// signalled, which can take forever if a device hangs. This function first polls the buffer
+    /// file descriptor for at most `timeout`, and only issues the ioctl once the fences relevant to
+    /// the access direction have signalled.
+    ///
+    /// Every successful call must be balanced by a call to [`DmaBuf::end_cpu_access`] with the
+    /// same mode.
+    ///
+    /// # Errors
+    ///
+    /// Will return [`MapError::Timeout`] if the fences didn't signal in time, or
+    /// [`MapError::FdAccess`] if either the poll or the ioctl fails.
+    pub fn begin_cpu_access_timeout(
+        &self,
+        mode: SyncMode,
+        timeout: Duration,
+    ) -> Result<(), MapError> {
+        debug!("Waiting at most {timeout:?} for the buffer to be ready for {mode:?} access");
+
+        let events = match mode {
+            SyncMode::Read => PollFlags::IN,
+            SyncMode::Write | SyncMode::ReadWrite => PollFlags::OUT,
+        };
+
+        if !poll_fd(self.as_fd(), events, Some(timeout))? {
+            debug!("Buffer fences didn't signal in time");
+            return Err(MapError::Timeout(timeout));
+        }
+
+        self.begin_cpu_access(mode)
+    }
+
+    /// Waits for the devices to be done writing to the buffer
+    ///
+    /// The buffer file descriptor becomes readable once all the write fences attached to the
+    /// buffer have signalled, which means that the producer is done and that the buffer can be
+    /// mapped for reading without blocking. This only waits for the fences, and doesn't
+    /// synchronize the caches: the buffer still needs to be mapped, or a CPU access started, to
+    /// be accessed.
+    ///
+    /// If `timeout` is `None`, this waits forever.
+    ///
+    /// Returns whether the fences have signalled before the timeout expired.
+    ///
+    /// # Errors
+    ///
+    /// Will return [`MapError::FdAccess`] if the poll call fails.
+    pub fn wait_readable(&self, timeout: Option<Duration>) -> Result<bool, MapError> {
+        debug!("Waiting at most {timeout:?} for the buffer write fences");
+
+        poll_fd(self.as_fd(), PollFlags::IN, timeout)
+    }
+
+    /// Waits asynchronously for the devices to be done writing to the buffer
+    ///
+    /// This is the asynchronous counterpart of [`DmaBuf::wait_readable`]: the buffer file
+    /// descriptor is registered with the tokio reactor for as long as the future is alive, and the
+    /// future resolves once all the write fences attached to the buffer have signalled.
+    ///
+    /// The reactor only allows a file descriptor to be registered once, so each call registers a
+    /// duplicate of the buffer file descriptor. Several tasks can thus wait for the same buffer
+    /// concurrently, at the cost of a file descriptor for each of them.
+    ///
+    /// # Panics
+    ///
+    /// Will panic if called outside of a tokio runtime with the IO driver enabled.
+    ///
+    /// # Errors
+    ///
+    /// Will return [`MapError::FdAccess`] if the file descriptor can't be duplicated, if it can't
+    /// be registered with the reactor, or if waiting for it fails.
+    #[cfg(feature = "tokio")]
+    pub async fn readable(&self) -> Result<(), MapError> {
+        let io_error = |e: std::io::Error| MapError::FdAccess {
+            reason: e.to_string(),
+            errno: e.raw_os_error().map(Errno::from_raw_os_error),
+            source: e,
+        };
+
+        debug!("Waiting asynchronously for the buffer write fences");
+
+        let fd = fcntl_dupfd_cloexec(self.as_fd(), 0).map_err(MapError::fd_access)?;
+        let fd = tokio::io::unix::AsyncFd::with_interest(fd, tokio::io::Interest::READABLE)
+            .map_err(io_error)?;
+
+        let _ready = fd.readable().await.map_err(io_error)?;
+
+        Ok(())
+    }
+
+    /// Prepares the buffer for a CPU access, retrying while a device holds the buffer
+    ///
+    /// The kernel can report that the buffer is still in use by a device with `EBUSY`, which is
+    /// usually transient. The access is attempted at most `attempts` times, waiting for `backoff`
+    /// between each attempt.
+    ///
+    /// Every successful call must be balanced by a call to [`DmaBuf::end_cpu_access`] with the
+    /// same mode.
+    ///
+    /// # Errors
+    ///
+    /// Will return [`MapError::Busy`] if the buffer was still busy after the last attempt, or
+    /// [`MapError::FdAccess`] if the ioctl fails for any other reason.
+    #[cfg(feature = "std")]
+    pub fn begin_cpu_access_retry(
+        &self,
+        mode: SyncMode,
+        attempts: usize,
+        backoff: Duration,
+    ) -> Result<(), MapError> {
+        let mut attempt = 1;
+
+        loop {
+            match self.begin_cpu_access(mode) {
+                Err(MapError::Busy) if attempt < attempts => {
+                    debug!("Buffer busy, retrying in {backoff:?} (attempt {attempt}/{attempts})");
+                    std::thread::sleep(backoff);
+                    attempt += 1;
+                }
+                ret => return ret,
+            }
+        }
+    }
+
+    /// Adds the buffer to an epoll set, to be notified when it's ready for a CPU access
+    ///
+    /// The buffer file descriptor becomes ready once the fences relevant to the access direction
+    /// have signalled: readable for [`SyncMode::Read`], writable otherwise. `token` will be
+    /// reported back in the events returned by `epoll_wait()`.
+    ///
+    /// # Errors
+    ///
+    /// Will return [`MapError::FdAccess`] if adding the buffer to the epoll set fails.
+    pub fn register_epoll(
+        &self,
+        epfd: BorrowedFd<'_>,
+        token: u64,
+        mode: SyncMode,
+    ) -> Result<(), MapError> {
+        let events = match mode {
+            SyncMode::Read => EventFlags::IN,
+            SyncMode::Write | SyncMode::ReadWrite => EventFlags::OUT,
+        };
+
+        epoll::add(epfd, &self.fd, EventData::new_u64(token), events).map_err(MapError::fd_access)
+    }
+
+    /// Prepares the buffer for a CPU access
+    ///
+    /// The mapping functions start and end the CPU access on their own, but this allows to
+    /// synchronize the caches for a buffer accessed through a pointer obtained elsewhere, such as
+    /// another mapping or a graphics API. This blocks until the fences relevant to the access
+    /// direction have signalled, see [`DmaBuf::begin_cpu_access_timeout`] to bound that wait.
+    ///
+    /// Every successful call must be balanced by a call to [`DmaBuf::end_cpu_access`] with the
+    /// same mode.
+    ///
+    /// # Errors
+    ///
+    /// Will return [`MapError::FdAccess`] if the underlying ioctl fails.
+    pub fn begin_cpu_access(&self, mode: SyncMode) -> Result<(), MapError> {
+        match mode {
+            SyncMode::Read => dma_buf_begin_cpu_read_access(self.as_fd()),
+            SyncMode::Write => dma_buf_begin_cpu_write_access(self.as_fd()),
+            SyncMode::ReadWrite => dma_buf_begin_cpu_readwrite_access(self.as_fd()),
+        }
+    }
+
+    /// Ends a CPU access started with [`DmaBuf::begin_cpu_access`] or
+    /// [`DmaBuf::begin_cpu_access_timeout`]
+    ///
+    /// # Errors
+    ///
+    /// Will return [`MapError::FdAccess`] if the underlying ioctl fails.
+    pub fn end_cpu_access(&self, mode: SyncMode) -> Result<(), MapError> {
+        match mode {
+            SyncMode::Read => dma_buf_end_cpu_read_access(self.as_fd()),
+            SyncMode::Write => dma_buf_end_cpu_write_access(self.as_fd()),
+            SyncMode::ReadWrite => dma_buf_end_cpu_readwrite_access(self.as_fd()),
+        }
+    }
+
+    /// Hands the ownership of the buffer over to the CPU
+    ///
+    /// This is the userspace counterpart of the kernel's `dma_sync_single_for_cpu()`: once it
+    /// returns, the devices are done with the buffer, the caches have been synchronized, and the
+    /// CPU can access the buffer in the direction given by `mode`. It is the same operation as
+    /// [`DmaBuf::begin_cpu_access`], and must be balanced by a call to
+    /// [`DmaBuf::sync_for_device`] with the same mode.
+    ///
+    /// # Errors
+    ///
+    /// Will return [`MapError::FdAccess`] if the underlying ioctl fails.
+    pub fn sync_for_cpu(&self, mode: SyncMode) -> Result<(), MapError> {
+        self.begin_cpu_access(mode)
+    }
+
+    /// Hands the ownership of the buffer back to the devices
+    ///
+    /// This is the userspace counterpart of the kernel's `dma_sync_single_for_device()`: once it
+    /// returns, the CPU writes have been flushed out of the caches, and the CPU must not access
+    /// the buffer anymore until the next call to [`DmaBuf::sync_for_cpu`]. It is the same
+    /// operation as [`DmaBuf::end_cpu_access`].
+    ///
+    /// # Errors
+    ///
+    /// Will return [`MapError::FdAccess`] if the underlying ioctl fails.
+    pub fn sync_for_device(&self, mode: SyncMode) -> Result<(), MapError> {
+        self.end_cpu_access(mode)
+    }
+
+    /// Imports the buffer backing a GBM buffer object
+    ///
+    /// A new file descriptor is exported from `bo` with `gbm_bo_get_fd()`, and the buffer object
+    /// can be dropped independently of the returned `DmaBuf`. The layout of the buffer (format,
+    /// stride, modifier) isn't carried over, and must be queried from `bo` if needed.
+    ///
+    /// # Errors
+    ///
+    /// Will return [`MapError::FdAccess`] if GBM couldn't export the buffer object.
+    #[cfg(feature = "gbm")]
+    pub fn from_gbm_bo<T>(bo: &gbm::BufferObject<T>) -> Result<Self, MapError> {
+        let fd = bo.fd().map_err(|e| MapError::FdAccess {
+            reason: e.to_string(),
+            source: std::io::Error::other(e),
+            errno: None,
+        })?;
+
+        debug!("Imported DMA-Buf from GBM buffer object, File Descriptor {fd:#?}");
+
+        Ok(Self::from(fd))
+    }
+
+    /// Exports the fences currently attached to the buffer as a `sync_file`
+    ///
+    /// This allows to coordinate with the devices accessing the buffer through explicit
+    /// synchronization: if `write` is set, the returned `sync_file` signals once all the pending
+    /// accesses to the buffer are done, and if only `read` is set, once the pending writes are.
+    ///
+    /// The returned file descriptor can be wrapped in a [`SyncFile`] to wait for it.
+    ///
+    /// # Errors
+    ///
+    /// Will return [`MapError::Unsupported`] if the kernel doesn't support exporting fences, or
+    /// [`MapError::FdAccess`] if the ioctl fails.
+    pub fn export_sync_file(&self, read: bool, write: bool) -> Result<OwnedFd, MapError> {
+        dma_buf_export_sync_file(self.as_fd(), read, write)
+    }
+
+    /// Attaches the fences of a `sync_file` to the buffer
+    ///
+    /// This governs the implicit synchronization of the buffer: the next users of the buffer
+    /// relying on implicit synchronization will wait for `fence`. If `write` is set, the fence is
+    /// added as a write fence, and all the next accesses will wait for it. If only `read` is set,
+    /// it's added as a read fence, and only the next writes will wait for it.
+    ///
+    /// # Errors
+    ///
+    /// Will return [`MapError::Unsupported`] if the kernel doesn't support importing fences, or
+    /// [`MapError::FdAccess`] if the ioctl fails.
+    pub fn import_sync_file(
+        &self,
+        fence: BorrowedFd<'_>,
+        read: bool,
+        write: bool,
+    ) -> Result<(), MapError> {
+        dma_buf_import_sync_file(self.as_fd(), fence, read, write)
+    }
+
+    /// Imports a `DmaBuf` from a file descriptor, checking that it's a DMA-Buf
+    ///
+    /// [`DmaBuf::from`] trusts that the file descriptor is a DMA-Buf, and any other file
+    /// descriptor will only be reported later by failing mmap calls or ioctls. This function
+    /// checks the magic of the filesystem backing the file descriptor instead, and fails right
+    /// away.
+    ///
+    /// # Errors
+    ///
+    /// Will return [`MapError::NotDmaBuf`] if the file descriptor isn't a DMA-Buf, or
+    /// [`MapError::FdAccess`] if it can't be checked. The file descriptor is closed in both cases.
+    pub fn try_from_fd(fd: OwnedFd) -> Result<Self, MapError> {
+        let stats = fstatfs(&fd).map_err(MapError::fd_access)?;
+
+        if stats.f_type.try_into() != Ok(DMA_BUF_MAGIC) {
+            debug!("File Descriptor {} isn't a DMA-Buf", fd.as_raw_fd());
+            return Err(MapError::NotDmaBuf);
+        }
+
+        Ok(Self::from(fd))
+    }
+
+    /// Creates a new `DmaBuf` referring to the same buffer
+    ///
+    /// The file descriptor is duplicated with `F_DUPFD_CLOEXEC`, so both `DmaBuf` can be closed
+    /// independently. They still share the same underlying buffer though: its content, its name,
+    /// its fences, and the cache synchronization issued through either of them.
+    ///
+    /// # Errors
+    ///
+    /// Will return [`MapError::FdAccess`] if the file descriptor can't be duplicated.
+    pub fn try_clone(&self) -> Result<Self, MapError> {
+        self.dup_cloexec()
+    }
+
+    fn with_fd(&self, fd: OwnedFd) -> Self {
+        Self {
+            fd,
+            len: AtomicUsize::new(self.len.load(Ordering::Relaxed)),
+        }
+    }
+
+    /// Creates a new `DmaBuf` referring to the same buffer, inherited across `exec()`
+    ///
+    /// The file descriptor is duplicated with `dup()`, and thus doesn't have the close-on-exec
+    /// flag, to pass the buffer to a program started with `exec()`. See [`DmaBuf::try_clone`] for
+    /// what both `DmaBuf` share.
+    ///
+    /// # Errors
+    ///
+    /// Will return [`MapError::FdAccess`] if the file descriptor can't be duplicated.
+    pub fn dup(&self) -> Result<Self, MapError> {
+        let fd = dup(self.as_fd()).map_err(MapError::fd_access)?;
+
+        Ok(self.with_fd(fd))
+    }
+
+    /// Creates a new `DmaBuf` referring to the same buffer, closed across `exec()`
+    ///
+    /// The file descriptor is duplicated with `F_DUPFD_CLOEXEC`, and thus has the close-on-exec
+    /// flag. This is what [`DmaBuf::try_clone`] does, see it for what both `DmaBuf` share.
+    ///
+    /// # Errors
+    ///
+    /// Will return [`MapError::FdAccess`] if the file descriptor can't be duplicated.
+    pub fn dup_cloexec(&self) -> Result<Self, MapError> {
+        let fd = fcntl_dupfd_cloexec(self.as_fd(), 0).map_err(MapError::fd_access)?;
+
+        Ok(self.with_fd(fd))
+    }
+
+    /// Sets the name of the buffer
+    ///
+    /// The name shows up in `/proc/*/fdinfo` and in the kernel debugfs `bufinfo` file, which
+    /// helps telling buffers apart when debugging.
+    ///
+    /// # Errors
+    ///
+    /// Will return [`MapError::InvalidName`] if the name is longer than 31 bytes, the kernel limit
+    /// once the NUL terminator is accounted for, or if it contains a NUL byte. Will return
+    /// [`MapError::Unsupported`] if the kernel doesn't support naming buffers, or
+    /// [`MapError::FdAccess`] if the ioctl fails.
+    pub fn set_name(&self, name: &str) -> Result<(), MapError> {
+        dma_buf_set_name(self.as_fd(), name)
+    }
+
+    /// Returns the statistics the kernel exposes about the buffer in sysfs
+    ///
+    /// The kernel exposes information about each buffer in `/sys/kernel/dmabuf/buffers/<inode>`
+    /// when built with `CONFIG_DMABUF_SYSFS_STATS`. Unlike debugfs' `bufinfo`, it's available on
+    /// production kernels.
+    ///
+    /// Returns `None` if the kernel doesn't expose those statistics, or if they can't be read.
+    #[must_use]
+    #[cfg(feature = "std")]
+    pub fn sysfs_stats(&self) -> Option<DmaBufStats> {
+        let stat = fstat(&self.fd).ok()?;
+        let path = format!("/sys/kernel/dmabuf/buffers/{}", stat.st_ino);
+
+        let size = std::fs::read_to_string(format!("{path}/size"))
+            .ok()?
+            .trim()
+            .parse()
+            .ok()?;
+
+        let exporter_name = std::fs::read_to_string(format!("{path}/exporter_name"))
+            .ok()?
+            .trim()
+            .to_owned();
+
+        Some(DmaBufStats {
+            size,
+            exporter_name,
+        })
+    }
+
+    /// Returns the access mode the buffer file descriptor was opened with
+    ///
+    /// Mapping a buffer with an access its file descriptor doesn't allow fails, so this allows to
+    /// pick the right mapping function beforehand: [`DmaBuf::memory_map_ro`] for
+    /// [`AccessMode::ReadOnly`]. A file descriptor opened [`AccessMode::WriteOnly`] can't be mapped
+    /// at all, since the kernel requires read access to map a file, even for a write-only mapping.
+    ///
+    /// # Errors
+    ///
+    /// Will return [`MapError::FdAccess`] if the file descriptor flags can't be retrieved.
+    pub fn access_mode(&self) -> Result<AccessMode, MapError> {
+        let mode = fcntl_getfl(&self.fd).map_err(MapError::fd_access)? & OFlags::RWMODE;
+
+        Ok(if mode == OFlags::RDWR {
+            AccessMode::ReadWrite
+        } else if mode == OFlags::WRONLY {
+            AccessMode::WriteOnly
+        } else {
+            AccessMode::ReadOnly
+        })
+    }
+
+    /// Returns the size of the buffer, in bytes
+    ///
+    /// The size is retrieved from the kernel, without mapping the buffer, the first time it's
+    /// needed, and cached afterwards. Use [`DmaBuf::refresh_len`] for buffers that can be resized.
+    ///
+    /// # Errors
+    ///
+    /// Will return [`MapError::FdAccess`] if the size can't be retrieved, or
+    /// [`MapError::TooLarge`] if it doesn't fit into an `usize`.
+    pub fn len(&self) -> Result<usize, MapError> {
+        match self.len.load(Ordering::Relaxed) {
+            0 => self.refresh_len(),
+            len => Ok(len),
+        }
+    }
+
+    /// Retrieves the size of the buffer from the kernel again, and returns it
+    ///
+    /// # Errors
+    ///
+    /// Will return [`MapError::FdAccess`] if the size can't be retrieved, or
+    /// [`MapError::TooLarge`] if it doesn't fit into an `usize`.
+    pub fn refresh_len(&self) -> Result<usize, MapError> {
+        let stat = fstat(&self.fd).map_err(MapError::fd_access)?;
+        let size = u64::try_from(stat.st_size)?;
+        let len = usize::try_from(size).map_err(|_e| MapError::TooLarge { size })?;
+
+        self.len.store(len, Ordering::Relaxed);
+
+        Ok(len)
+    }
+
+    /// Returns whether the buffer is empty
+    ///
+    /// # Errors
+    ///
+    /// Will return an error if the buffer size can't be retrieved.
+    pub fn is_empty(&self) -> Result<bool, MapError> {
+        Ok(self.len()? == 0)
+    }
+
+    /// Checks that the buffer size is a multiple of the page size
+    ///
+    /// Mappings are always rounded up to a page multiple, so a buffer with an unaligned size can
+    /// still be mapped, but some pipelines consider such a buffer as a misconfigured allocation.
+    /// This allows them to fail early.
+    ///
+    /// # Errors
+    ///
+    /// Will return [`MapError::UnalignedSize`] if the buffer size isn't a multiple of the page
+    /// size, or an error if the buffer size can't be retrieved.
+    pub fn require_page_aligned_size(&self) -> Result<(), MapError> {
+        let size = self.len()?;
+        let alignment = page_size();
+
+        if !size.is_multiple_of(alignment) {
+            return Err(MapError::UnalignedSize { size, alignment });
+        }
+
+        Ok(())
+    }
+
+    /// Returns the alignment the offset of an mmap call on this buffer must satisfy
+    ///
+    /// The kernel rejects any mmap offset that isn't a multiple of this value with `EINVAL`.
+    /// DMA-Buf exporters map buffers with regular pages, so this is the system page size. The
+    /// mappings created by this crate map the whole pages the requested range spans, so their
+    /// offset doesn't need to be aligned, but their address does.
+    #[must_use]
+    pub fn offset_alignment(&self) -> usize {
+        page_size()
+    }
+
+    fn mmap(
+        &self,
+        prot: ProtFlags,
+        flags: MapFlags,
+        address: *mut c_void,
+        offset: usize,
+        mapped_len: usize,
+        retries: u32,
+    ) -> Result<*mut u8, MapError> {
+        debug!("Mapping DMA-Buf buffer with File Descriptor {:#?}", self.fd);
+
+        let offset = u64::try_from(offset)?;
+        let mut attempt = 0;
+
+        let mapping_ptr = loop {
+            // SAFETY: It's unclear at this point what the exact safety requirements from mmap are,
+            // but our fd is valid and the length and offset are aligned, so that's something.
+            match unsafe { mmap(address, mapped_len, prot, flags, &self.fd, offset) } {
+                Err(Errno::AGAIN) if attempt < retries => {
+                    attempt += 1;
+                    debug!("mmap failed with EAGAIN, retrying (attempt {attempt}/{retries})");
+
+                    #[cfg(feature = "std")]
+                    std::thread::sleep(MMAP_RETRY_BACKOFF);
+                }
+                ret => break ret,
+            }
+        }
+        .map(<*mut c_void>::cast::<u8>)
+        .map_err(|e| {
+            if e == Errno::NOMEM {
+                MapError::out_of_memory(e)
+            } else {
+                MapError::mapping_failed(e)
+            }
+        })?;
+
+        debug!("Memory Mapping Done");
+
+        Ok(mapping_ptr)
+    }
+
+    /// Maps a `DmaBuf` for the CPU to access it
+    ///
+    /// # Errors
+    ///
+    /// Will return an error if either the Buffer's length can't be retrieved, or if the mmap call
+    /// fails. If the buffer is too large to be mapped, [`MapError::TooLarge`] will be returned, and
+    /// if the mmap call fails because the kernel ran out of memory, [`MapError::OutOfMemory`] will
+    /// be returned.
+    pub fn memory_map(self) -> Result<MappedDmaBuf, MapError> {
+        let Layout {
+            len, mapped_len, ..
+        } = mapping_layout(self.len()?, 0, 0, None, page_size())?;
+        let mmap = self.mmap(
+            ProtFlags::READ | ProtFlags::WRITE,
+            MapFlags::SHARED,
+            ptr::null_mut(),
+            0,
+            mapped_len,
+            MMAP_RETRIES,
+        )?;
+
+        Ok(MappedDmaBuf {
+            buf: self,
+            len,
+            mapped_len,
+            mmap,
+        })
+    }
+
+    /// Maps a `DmaBuf` for the CPU to read it
+    ///
+    /// The buffer is mapped read-only, so it works for buffers exported, or file descriptors
+    /// opened, without write access. The CPU read access starts when the buffer is mapped, and ends
+    /// when the returned [`MappedDmaBufRo`] is dropped.
+    ///
+    /// Since the buffer is only borrowed, it can be mapped multiple times in independent places.
+    ///
+    /// # Errors
+    ///
+    /// Will return an error if either the Buffer's length can't be retrieved, if the mmap call
+    /// fails, or if the CPU access can't be started.
+    pub fn memory_map_ro(&self) -> Result<MappedDmaBufRo<'_>, MapError> {
+        MapOptions::new().map(self)
+    }
+
+    /// Maps a part of a `DmaBuf` for the CPU to read it
+    ///
+    /// Only the `len` bytes starting at `offset` are mapped, and the slice returned by
+    /// [`MappedDmaBufRo::as_slice`] is exactly `len` bytes long. `offset` doesn't need to
+    /// be aligned, the whole pages the range spans are mapped. See [`DmaBuf::memory_map_ro`] for
+    /// the rest.
+    ///
+    /// # Errors
+    ///
+    /// Will return [`MapError::RangeOutOfBounds`] if the range doesn't fit in the buffer, or an
+    /// error if either the mmap call fails or the CPU access can't be started.
+    pub fn memory_map_ro_range(
+        &self,
+        offset: usize,
+        len: usize,
+    ) -> Result<MappedDmaBufRo<'_>, MapError> {
+        MapOptions::new().offset(offset).len(len).map(self)
+    }
+
+    /// Maps a `DmaBuf` for the CPU to read it, prefaulting the mapping
+    ///
+    /// Behaves like [`DmaBuf::memory_map_ro`], but the mapping is created with `MAP_POPULATE`
+    /// so that all its pages are faulted in by the mmap call itself. The mapping setup takes
+    /// longer, proportionally to the buffer size, but the first access to each page doesn't fault
+    /// anymore, which keeps the latency of the accesses deterministic.
+    ///
+    /// # Errors
+    ///
+    /// Will return an error if either the Buffer's length can't be retrieved, if the mmap call
+    /// fails, or if the CPU access can't be started.
+    pub fn memory_map_ro_populated(&self) -> Result<MappedDmaBufRo<'_>, MapError> {
+        MapOptions::new().populate(true).map(self)
+    }
+
+    /// Maps a `DmaBuf` of `len` bytes for the CPU to read it
+    ///
+    /// The size of the buffer isn't retrieved from the kernel, and `len` is used instead. See
+    /// [`MapOptions::buffer_len`] for why.
+    ///
+    /// # Safety
+    ///
+    /// `len` must not be larger than the actual size of the buffer, see
+    /// [`MapOptions::buffer_len`].
+    ///
+    /// # Errors
+    ///
+    /// Will return [`MapError::EmptyBuffer`] if `len` is 0, or an error if either the mmap call
+    /// fails, or the CPU access can't be started.
+    pub unsafe fn memory_map_ro_with_len(
+        &self,
+        len: usize,
+    ) -> Result<MappedDmaBufRo<'_>, MapError> {
+        // SAFETY: Our caller guarantees that len fits in the buffer.
+        unsafe { MapOptions::new().buffer_len(len) }.map(self)
+    }
+
+    /// Maps a `DmaBuf` for the CPU to read it, in a mapping that can be shared between readers
+    ///
+    /// See [`SharedMapping`]. The CPU read access starts when the buffer is mapped, and ends when
+    /// the last clone of the returned [`SharedMapping`] is dropped.
+    ///
+    /// # Errors
+    ///
+    /// Will return an error if either the Buffer's length can't be retrieved, if the mmap call
+    /// fails, or if the CPU access can't be started.
+    pub fn memory_map_shared_ro(&self) -> Result<SharedMapping<'_>, MapError> {
+        MapOptions::new().map(self).map(SharedMapping::new)
+    }
+
+    /// Maps a `DmaBuf` for the CPU to read from and write to it
+    ///
+    /// The CPU read and write access starts when the buffer is mapped, and ends when the returned
+    /// [`MappedDmaBufRw`] is dropped.
+    ///
+    /// # Errors
+    ///
+    /// Will return an error if either the Buffer's length can't be retrieved, if the mmap call
+    /// fails, or if the CPU access can't be started.
+    pub fn memory_map_rw(&mut self) -> Result<MappedDmaBufRw<'_>, MapError> {
+        MapOptions::new().map_mut(self)
+    }
+
+    /// Maps a part of a `DmaBuf` for the CPU to read from and write to it
+    ///
+    /// Only the `len` bytes starting at `offset` are mapped. `offset` doesn't need to
+    /// be aligned, the whole pages the range spans are mapped. See [`DmaBuf::memory_map_rw`] for
+    /// the rest.
+    ///
+    /// # Errors
+    ///
+    /// Will return [`MapError::RangeOutOfBounds`] if the range doesn't fit in the buffer, or an
+    /// error if either the mmap call fails or the CPU access can't be started.
+    pub fn memory_map_rw_range(
+        &mut self,
+        offset: usize,
+        len: usize,
+    ) -> Result<MappedDmaBufRw<'_>, MapError> {
+        MapOptions::new().offset(offset).len(len).map_mut(self)
+    }
+
+    /// Maps a `DmaBuf` for the CPU to read from and write to it, prefaulting the mapping
+    ///
+    /// Behaves like [`DmaBuf::memory_map_rw`], but the mapping is created with `MAP_POPULATE`.
+    /// See [`DmaBuf::memory_map_ro_populated`].
+    ///
+    /// # Errors
+    ///
+    /// Will return an error if either the Buffer's length can't be retrieved, if the mmap call
+    /// fails, or if the CPU access can't be started.
+    pub fn memory_map_rw_populated(&mut self) -> Result<MappedDmaBufRw<'_>, MapError> {
+        MapOptions::new().populate(true).map_mut(self)
+    }
+
+    /// Maps a `DmaBuf` of `len` bytes for the CPU to read from and write to it
+    ///
+    /// See [`DmaBuf::memory_map_ro_with_len`].
+    ///
+    /// # Safety
+    ///
+    /// `len` must not be larger than the actual size of the buffer, see
+    /// [`MapOptions::buffer_len`].
+    ///
+    /// # Errors
+    ///
+    /// Will return [`MapError::EmptyBuffer`] if `len` is 0, or an error if either the mmap call
+    /// fails, or the CPU access can't be started.
+    pub unsafe fn memory_map_rw_with_len(
+        &mut self,
+        len: usize,
+    ) -> Result<MappedDmaBufRw<'_>, MapError> {
+        // SAFETY: Our caller guarantees that len fits in the buffer.
+        unsafe { MapOptions::new().buffer_len(len) }.map_mut(self)
+    }
+
+    /// Maps a private, copy-on-write, copy of a `DmaBuf` for the CPU to read from and write to it
+    ///
+    /// The buffer is mapped with `MAP_PRIVATE`: the mapping initially shows the buffer content,
+    /// but any page written to is copied first, and the writes never reach the buffer itself.
+    /// Since the buffer is never modified through this mapping, it only needs to be borrowed
+    /// immutably, and only a CPU read access is started when it's mapped, and ended when the
+    /// returned [`MappedDmaBufRw`] is dropped.
+    ///
+    /// Some exporters only allow shared mappings, and will make the mmap call fail.
+    ///
+    /// # Errors
+    ///
+    /// Will return an error if either the Buffer's length can't be retrieved, if the mmap call
+    /// fails, or if the CPU access can't be started.
+    pub fn memory_map_copy(&self) -> Result<MappedDmaBufRw<'_>, MapError> {
+        MapOptions::new().map_copy(self)
+    }
+
+    /// Maps a `DmaBuf` for the CPU to write to it
+    ///
+    /// The buffer is only mapped for writing, which documents the intent, but the file descriptor
+    /// still needs to allow reading: the kernel refuses to map files opened write-only, whatever
+    /// the protection requested. The CPU write access starts when the buffer is mapped, and ends
+    /// when the returned [`MappedDmaBufWo`] is dropped.
+    ///
+    /// # Errors
+    ///
+    /// Will return an error if either the Buffer's length can't be retrieved, if the mmap call
+    /// fails, or if the CPU access can't be started.
+    pub fn memory_map_wo(&mut self) -> Result<MappedDmaBufWo<'_>, MapError> {
+        MapOptions::new().map_wo(self)
+    }
+
+    /// Maps a part of a `DmaBuf` for the CPU to write to it
+    ///
+    /// Only the `len` bytes starting at `offset` are mapped. `offset` doesn't need to
+    /// be aligned, the whole pages the range spans are mapped. See [`DmaBuf::memory_map_wo`] for
+    /// the rest.
+    ///
+    /// # Errors
+    ///
+    /// Will return [`MapError::RangeOutOfBounds`] if the range doesn't fit in the buffer, or an
+    /// error if either the mmap call fails or the CPU access can't be started.
+    pub fn memory_map_wo_range(
+        &mut self,
+        offset: usize,
+        len: usize,
+    ) -> Result<MappedDmaBufWo<'_>, MapError> {
+        MapOptions::new().offset(offset).len(len).map_wo(self)
+    }
+
+    /// Maps a `DmaBuf` for the CPU to write to it, prefaulting the mapping
+    ///
+    /// Behaves like [`DmaBuf::memory_map_wo`], but the mapping is created with `MAP_POPULATE`.
+    /// See [`DmaBuf::memory_map_ro_populated`].
+    ///
+    /// # Errors
+    ///
+    /// Will return an error if either the Buffer's length can't be retrieved, if the mmap call
+    /// fails, or if the CPU access can't be started.
+    pub fn memory_map_wo_populated(&mut self) -> Result<MappedDmaBufWo<'_>, MapError> {
+        MapOptions::new().populate(true).map_wo(self)
+    }
+
+    /// Maps a `DmaBuf` both for the CPU to write to it, and for the CPU to read it
+    ///
+    /// This allows a producer and a consumer within the same process to share a single buffer,
+    /// each through its own mapping, with its own CPU access: the write access ends when the
+    /// [`MappedDmaBufWo`] is released or dropped, and the read access when the
+    /// [`MappedDmaBufRo`] is.
+    ///
+    /// The mappings are independent, and nothing orders the accesses between them: the caller is
+    /// responsible for sequencing the writes before the reads that depend on them.
+    ///
+    /// # Safety
+    ///
+    /// The read-only mapping hands out shared references to the buffer content, which must not
+    /// change while they're alive. The caller must make sure that the buffer isn't written to
+    /// through the write-only mapping while any reference obtained from the read-only mapping,
+    /// through [`MappedDmaBufRo::as_slice`], [`Deref`], [`std::io::Read`] or any other accessor,
+    /// is alive, on any thread.
+    ///
+    /// # Errors
+    ///
+    /// Will return an error if either the Buffer's length can't be retrieved, if either mmap call
+    /// fails, or if either CPU access can't be started.
+    pub unsafe fn split_access(
+        &mut self,
+    ) -> Result<(MappedDmaBufWo<'_>, MappedDmaBufRo<'_>), MapError> {
+        let buf = &*self;
+        let wo = MapOptions::new().map_write(buf)?;
+        let ro = MapOptions::new().map(buf)?;
+
+        Ok((wo, ro))
+    }
+
+    /// Maps a `DmaBuf` of `len` bytes for the CPU to write to it
+    ///
+    /// See [`DmaBuf::memory_map_ro_with_len`].
+    ///
+    /// # Safety
+    ///
+    /// `len` must not be larger than the actual size of the buffer, see
+    /// [`MapOptions::buffer_len`].
+    ///
+    /// # Errors
+    ///
+    /// Will return [`MapError::EmptyBuffer`] if `len` is 0, or an error if either the mmap call
+    /// fails, or the CPU access can't be started.
+    pub unsafe fn memory_map_wo_with_len(
+        &mut self,
+        len: usize,
+    ) -> Result<MappedDmaBufWo<'_>, MapError> {
+        // SAFETY: Our caller guarantees that len fits in the buffer.
+        unsafe { MapOptions::new().buffer_len(len) }.map_wo(self)
+    }
+
+    /// Copies the content of the buffer into `dst`
+    ///
+    /// The buffer is mapped for reading and `dst` for writing, each with its own CPU access, for
+    /// the duration of the copy. The whole buffer is copied at the start of `dst`, and the number
+    /// of bytes copied is returned. If `dst` is larger, the rest of its content is left untouched.
+    ///
+    /// # Errors
+    ///
+    /// Will return [`MapError::RangeOutOfBounds`] if `dst` is smaller than the buffer, or an error
+    /// if mapping or releasing either buffer fails.
+    pub fn copy_to(&self, dst: &mut Self) -> Result<usize, MapError> {
+        let len = self.len()?;
+        checked_subrange(0, len, dst.len()?)?;
+
+        let src_mapping = self.memory_map_ro()?;
+        let mut dst_mapping = dst.memory_map_wo()?;
+        dst_mapping.copy_from_slice(src_mapping.as_slice())?;
+
+        let dst_released = dst_mapping.release();
+        let src_released = src_mapping.release();
+        dst_released.and(src_released)?;
+
+        Ok(len)
+    }
+
+    /// Copies the content of the buffer into a new buffer allocated from `heap`
+    ///
+    /// This gives a private, writable, copy of a buffer that might only be readable, such as a
+    /// buffer imported from another process or device. The new buffer has the same length, and
+    /// the copy is done with [`DmaBuf::copy_to`].
+    ///
+    /// # Errors
+    ///
+    /// Will return [`MapError::FdAccess`] if the allocation fails, or an error if the length of
+    /// the buffer can't be retrieved, or if the copy fails.
+    pub fn clone_contents_to_heap(
+        &self,
+        heap: &DmaHeap,
+        flags: HeapFlags,
+    ) -> Result<Self, MapError> {
+        let mut clone = heap.alloc(self.len()?, flags)?;
+
+        self.copy_to(&mut clone)?;
+
+        Ok(clone)
+    }
+
+    /// Copies the first `len` bytes of the buffer to `dst`
+    ///
+    /// The copy is done by the kernel with `splice()`, without going through a mapping. `splice()`
+    /// requires one of the two file descriptors to be a pipe though, and most exporters don't
+    /// support it anyway, so if the kernel rejects it with `EINVAL`, the buffer is mapped for
+    /// reading instead, and its content written to `dst`.
+    ///
+    /// `dst` is written to at its current offset. The number of bytes copied is returned, which
+    /// is smaller than `len` if the buffer is.
+    ///
+    /// # Errors
+    ///
+    /// Will return [`MapError::FdAccess`] if either `splice()` or writing to `dst` fails, or an
+    /// error if the buffer size can't be retrieved, if mapping the buffer fails, or if releasing
+    /// the mapping fails.
+    pub fn splice_to(&self, dst: BorrowedFd<'_>, len: usize) -> Result<usize, MapError> {
+        let len = len.min(self.len()?);
+        let mut offset = 0;
+        let mut copied = 0;
+
+        while copied < len {
+            match splice(
+                &self.fd,
+                Some(&mut offset),
+                dst,
+                None,
+                len - copied,
+                SpliceFlags::empty(),
+            ) {
+                Ok(0) => break,
+                Ok(count) => copied += count,
+                Err(Errno::INTR) => {}
+                Err(Errno::INVAL) if copied == 0 => {
+                    debug!("splice isn't supported, falling back to a mapped copy");
+                    return self.write_to(dst, len);
+                }
+                Err(e) => return Err(MapError::fd_access(e)),
+            }
+        }
+
+        Ok(copied)
+    }
+
+    fn write_to(&self, dst: BorrowedFd<'_>, len: usize) -> Result<usize, MapError> {
+        let mapping = self.memory_map_ro_range(0, len)?;
+        let mut written = 0;
+
+        while written < len {
+            match write(dst, &mapping.as_slice()[written..]) {
+                Ok(0) => break,
+                Ok(count) => written += count,
+                Err(Errno::INTR) => {}
+                Err(e) => return Err(MapError::fd_access(e)),
+            }
+        }
+
+        mapping.release()?;
+
+        Ok(written)
+    }
+
+    /// Copies the content of the buffer into a new `Vec`
+    ///
+    /// The buffer is mapped for reading for the duration of the copy, and the returned `Vec` is
+    /// as long as the buffer.
+    ///
+    /// # Errors
+    ///
+    /// Will return an error if mapping the buffer fails, or if releasing the mapping fails.
+    pub fn to_vec(&self) -> Result<Vec<u8>, MapError> {
+        self.with_read(<[u8]>::to_vec)
+    }
+
+    /// Fills the buffer with the data read from `src`
+    ///
+    /// The buffer is mapped for writing for the duration of the transfer, and `src` is read from
+    /// until either the buffer is full or `src` reaches its end. The number of bytes read is
+    /// returned, and is smaller than the buffer if `src` was shorter.
+    ///
+    /// # Errors
+    ///
+    /// Will return [`MapError::Io`] if reading from `src` fails, or an error if mapping the buffer
+    /// fails, or if releasing the mapping fails.
+    #[cfg(feature = "std")]
+    pub fn fill_from_reader(&mut self, src: &mut impl std::io::Read) -> Result<usize, MapError> {
+        let mut mapping = self.memory_map_wo()?;
+        let len = mapping.len();
+
+        // The mapping can't be handed over to the reader, since nothing prevents a Read
+        // implementation from reading the buffer it's given, and the caches aren't synchronized
+        // for reading. The data goes through a buffer on the stack instead.
+        let mut chunk = [0; FILL_CHUNK_LEN];
+        let mut filled = 0;
+        while filled < len {
+            let max = chunk.len().min(len - filled);
+
+            match src.read(&mut chunk[..max]) {
+                Ok(0) => break,
+                Ok(count) => filled += mapping.write_at(filled, &chunk[..count])?,
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(MapError::Io(e)),
+            }
+        }
+
+        mapping.release()?;
+
+        Ok(filled)
+    }
+
+    /// Calls a closure to read the buffer content
+    ///
+    /// The buffer is mapped for reading, the closure is called with its content, and the
+    /// mapping is then released, in that order. The result of the closure is returned.
+    ///
+    /// # Errors
+    ///
+    /// Will return an error if mapping the buffer fails, or if releasing the mapping fails, even
+    /// if the closure succeeded.
+    pub fn with_read<R>(&self, f: impl FnOnce(&[u8]) -> R) -> Result<R, MapError> {
+        let mapping = self.memory_map_ro()?;
+        let ret = f(mapping.as_slice());
+        mapping.release()?;
+
+        Ok(ret)
+    }
+
+    /// Calls a closure to read from and write to the buffer content
+    ///
+    /// The buffer is mapped for reading and writing, the closure is called with its content, and
+    /// the mapping is then released, in that order. The result of the closure is returned.
+    ///
+    /// # Errors
+    ///
+    /// Will return an error if mapping the buffer fails, or if releasing the mapping fails, even
+    /// if the closure succeeded.
+    pub fn with_readwrite<R>(&mut self, f: impl FnOnce(&mut [u8]) -> R) -> Result<R, MapError> {
+        let mut mapping = self.memory_map_rw()?;
+        let ret = f(mapping.as_slice_mut());
+        mapping.release()?;
+
+        Ok(ret)
+    }
+
+    /// Calls a closure to write to the buffer content
+    ///
+    /// The buffer is mapped for writing, the closure is called with its content, and the mapping
+    /// is then released, in that order. The result of the closure is returned.
+    ///
+    /// The caches are only synchronized for a write access, so the closure is given a slice of
+    /// [`MaybeUninit`] bytes, like [`MappedDmaBufWo::as_slice_mut`] returns.
+    ///
+    /// # Errors
+    ///
+    /// Will return an error if mapping the buffer fails, or if releasing the mapping fails, even
+    /// if the closure succeeded.
+    pub fn with_write<R>(
+        &mut self,
+        f: impl FnOnce(&mut [MaybeUninit<u8>]) -> R,
+    ) -> Result<R, MapError> {
+        let mut mapping = self.memory_map_wo()?;
+        let ret = f(mapping.as_slice_mut());
+        mapping.release()?;
+
+        Ok(ret)
+    }
+}
+
+/// A `DmaBuf` mapped in memory
+///
+/// Unlike the guards returned by [`DmaBuf::memory_map_ro`], [`DmaBuf::memory_map_rw`] and
+/// [`DmaBuf::memory_map_wo`], a `MappedDmaBuf` owns the buffer and keeps it mapped for its whole
+/// lifetime, and only synchronizes the caches around each access. It's thus well suited to
+/// streaming workloads accessing a buffer many times: the costly mmap only happens once, and each
+/// access only costs the `DMA_BUF_IOCTL_SYNC` ioctls. The buffer is unmapped when the
+/// `MappedDmaBuf` is dropped.
+///
+/// # Fork
+///
+/// Both the mapping and the buffer file descriptor are inherited by a child process across
+/// `fork()`, and since the cache synchronization is tied to the open file description, the parent
+/// and the child share it.
+///
+/// Every access through [`MappedDmaBuf::read`], [`MappedDmaBuf::readwrite`] or
+/// [`MappedDmaBuf::write`] issues its own begin and end `DMA_BUF_IOCTL_SYNC` ioctls, so no access
+/// window is ever left open across a `fork()`, and the child doesn't have anything to re-arm
+/// before accessing the buffer. However, the kernel doesn't coordinate concurrent accesses from
+/// the parent and the child, so the processes must make sure their accesses don't overlap.
+pub struct MappedDmaBuf {
+    buf: DmaBuf,
+    len: usize,
+    mapped_len: usize,
+    mmap: *mut u8,
+}
+
+/// Former error type to access a [`MappedDmaBuf`]
+#[deprecated(note = "All the fallible functions now return MapError")]
+pub type BufferError = MapError;
+
+impl MappedDmaBuf {
+    fn as_slice(&self) -> &[u8] {
+        // SAFETY: We know that the pointer is valid, and the buffer length is at least equal to
+        // self.len bytes. The backing buffer won't be mutated by the kernel, our structure is the
+        // sole owner of the pointer, and it won't be mutated in our code either, so we're safe.
+        unsafe { slice::from_raw_parts(self.mmap, self.len) }
+    }
+
+    fn as_slice_mut(&mut self) -> &mut [u8] {
+        // SAFETY: We know that the pointer is valid, and the buffer length is at least equal to
+        // self.len bytes. The backing buffer won't be mutated by the kernel, our structure is the
+        // sole owner of the pointer, and it won't be mutated in our code either, so we're safe.
+        unsafe { slice::from_raw_parts_mut(self.mmap, self.len) }
+    }
+
+    /// Calls a closure to read the buffer content
+    ///
+    /// DMA-Buf requires the user-space to call the `DMA_BUF_IOCTL_SYNC` ioctl before and after any
+    /// CPU access to a buffer in order to maintain the cache coherency. The closure will be run
+    /// with those primitives called for a read access from the CPU.
+    ///
+    /// Since the caches are invalidated on every call, this is the right primitive to poll from
+    /// the CPU a buffer that a device keeps updating: each call sees the latest data written by
+    /// the device, even on platforms that aren't cache-coherent. Keep in mind that each call
+    /// costs two ioctls, and possibly a cache invalidation of the whole buffer.
+    ///
+    /// The result of the closure will be returned.
+    ///
+    /// # Errors
+    ///
+    /// Will return [`MapError`] if the underlying ioctl or the closure fails
+    pub fn read<A, F, R>(&self, f: F, arg: Option<A>) -> Result<R, MapError>
+    where
+        F: Fn(&[u8], Option<A>) -> Result<R, Box<dyn core::error::Error + Send + Sync>>,
+    {
+        debug!("Preparing the buffer for read access");
+
+        dma_buf_begin_cpu_read_access(self.buf.as_fd())?;
+
+        debug!("Accessing the buffer");
+
+        let ret = {
+            let bytes = self.as_slice();
+
+            f(bytes, arg)
+                .inspect(|_| {
+                    debug!("Closure done without error");
+                })
+                .map_err(|e| {
+                    debug!("Closure encountered an error {e}");
+                    MapError::Closure(e)
+                })
+        };
+
+        dma_buf_end_cpu_read_access(self.buf.as_fd())?;
+
+        debug!("Buffer access done");
+
+        ret
+    }
+
+    /// Calls a closure to read from and write to the buffer content
+    ///
+    /// DMA-Buf requires the user-space to call the `DMA_BUF_IOCTL_SYNC` ioctl before and after any
+    /// CPU access to a buffer in order to maintain the cache coherency. The closure will be run
+    /// with those primitives called for a read and write access from the CPU.
+    ///
+    /// The result of the closure will be returned on success. On failure, the error returned by the
+    /// closure will be wrapped in [`MapError::Closure`].
+    ///
+    /// # Errors
+    ///
+    /// Will return [`MapError`] if the underlying ioctl or the closure fails
+    pub fn readwrite<A, F, R>(&mut self, f: F, arg: Option<A>) -> Result<R, MapError>
+    where
+        F: Fn(&mut [u8], Option<A>) -> Result<R, Box<dyn core::error::Error + Send + Sync>>,
+    {
+        debug!("Preparing the buffer for read/write access");
+
+        dma_buf_begin_cpu_readwrite_access(self.buf.as_fd())?;
+
+        debug!("Accessing the buffer");
+
+        let ret = {
+            let bytes = self.as_slice_mut();
+
+            f(bytes, arg)
+                .inspect(|_| {
+                    debug!("Closure done without error");
+                })
+                .map_err(|e| {
+                    debug!("Closure encountered an error {e}");
+                    MapError::Closure(e)
+                })
+        };
+
+        dma_buf_end_cpu_readwrite_access(self.buf.as_fd())?;
+
+        debug!("Buffer access done");
+
+        ret
+    }
+
+    /// Calls a closure to read from and write to the buffer content
+    ///
+    /// DMA-Buf requires the user-space to call the `DMA_BUF_IOCTL_SYNC` ioctl before and after any
+    /// CPU access to a buffer in order to maintain the cache coherency. The closure will be run
+    /// with those primitives called for a read and write access from the CPU.
+    ///
+    /// The closure must return () on success. On failure, the error returned by the closure will be
+    /// wrapped in [`MapError::Closure`].
+    ///
+    /// # Errors
+    ///
+    /// Will return [`MapError`] if the underlying ioctl or the closure fails
+    pub fn write<A, F>(&mut self, f: F, arg: Option<A>) -> Result<(), MapError>
+    where
+        F: Fn(&mut [u8], Option<A>) -> Result<(), Box<dyn core::error::Error + Send + Sync>>,
+    {
+        debug!("Preparing the buffer for write access");
+
+        dma_buf_begin_cpu_write_access(self.buf.as_fd())?;
+
+        debug!("Accessing the buffer");
+
+        let ret = {
+            let bytes = self.as_slice_mut();
+
+            f(bytes, arg)
+                .map(|()| {
+                    debug!("Closure done without error");
+                })
+                .map_err(|e| {
+                    debug!("Closure encountered an error {e}");
+                    MapError::Closure(e)
+                })
+        };
+
+        dma_buf_end_cpu_write_access(self.buf.as_fd())?;
+
+        debug!("Buffer access done");
+
+        ret
+    }
+
+    /// Gives up the ownership of the mapping, without unmapping it
+    ///
+    /// The buffer file descriptor is closed, but the mapping stays valid until it's unmapped, and
+    /// the pointer and length of the mapping are returned so that foreign code can take over and
+    /// eventually call `munmap()` itself. The length is the one of the whole mapping, rounded up
+    /// to the page size, and not the buffer size.
+    ///
+    /// Since accesses through [`MappedDmaBuf::read`], [`MappedDmaBuf::readwrite`] and
+    /// [`MappedDmaBuf::write`] always end their cache synchronization, no access to the buffer is
+    /// in progress when this is called.
+    #[must_use]
+    pub fn forget_unmap(self) -> (*mut u8, usize) {
+        let this = ManuallyDrop::new(self);
+
+        // SAFETY: this is wrapped in a ManuallyDrop and is never used again, so the buffer will
+        // only be dropped once, here.
+        drop(unsafe { ptr::read(&raw const this.buf) });
+
+        (this.mmap, this.mapped_len)
+    }
+
+    /// Counts the occurrences of each byte value in the buffer
+    ///
+    /// The buffer is accessed for reading with the proper cache synchronization, so the histogram
+    /// reflects what the devices wrote. This is mostly useful to check whether a buffer was
+    /// written at all, or is still filled with zeros.
+    ///
+    /// # Errors
+    ///
+    /// Will return [`MapError`] if the underlying ioctl fails
+    pub fn byte_histogram(&self) -> Result<[u64; 256], MapError> {
+        self.read(
+            |bytes, _: Option<()>| {
+                let mut histogram = [0; 256];
+
+                for byte in bytes {
+                    histogram[usize::from(*byte)] += 1;
+                }
+
+                Ok(histogram)
+            },
+            None,
+        )
+    }
+}
+
+/// A `DmaBuf` mapped in memory for the CPU to access it
+///
+/// The access granted to the CPU is set by `M`: [`Ro`] to read the buffer, [`Rw`] to read from and
+/// write to it, and [`Wo`] to write to it. [`MappedDmaBufRo`], [`MappedDmaBufRw`] and
+/// [`MappedDmaBufWo`] are the shortcuts for each of them, and document what each access allows.
+///
+/// The CPU access to the buffer starts when it's mapped, and ends when the mapping is released or
+/// dropped.
+pub struct Mapping<'a, M: Access> {
+    buf: &'a DmaBuf,
+    len: usize,
+    mapped_len: usize,
+    mmap: *mut u8,
+    delta: usize,
+    // Only used by the std::io implementations
+    #[cfg_attr(not(feature = "std"), allow(dead_code))]
+    pos: usize,
+    sync: bool,
+    access: SyncMode,
+    mode: PhantomData<M>,
+}
+
+impl<'a, M: Access> Mapping<'a, M> {
+    fn new(buf: &'a DmaBuf, mmap: *mut u8, layout: Layout, sync: bool) -> Self {
+        Self {
+            buf,
+            len: layout.len,
+            mapped_len: layout.mapped_len,
+            mmap: mmap.wrapping_add(layout.delta),
+            delta: layout.delta,
+            pos: 0,
+            sync,
+            access: M::SYNC,
+            mode: PhantomData,
+        }
+    }
+
+    /// Returns the length of the mapping, in bytes
+    ///
+    /// This is the length of the data the mapping covers, which the slices, the pointers and the
+    /// offsets of the accessors are bounded by.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns the length of the memory region mapped, in bytes
+    ///
+    /// The mmap call always maps whole pages, so this is the length of the pages spanned by the
+    /// [`Mapping::len`] bytes of the mapping, a multiple of the page size. This is the length the
+    /// syscalls operating on the region, like `madvise()`, `msync()` or `mlock()`, expect, and the
+    /// one used by the functions wrapping them. The bytes past [`Mapping::len`] aren't part of the
+    /// buffer data, and must not be relied upon.
+    #[must_use]
+    pub fn mapped_len(&self) -> usize {
+        self.mapped_len
+    }
+
+    /// Returns whether the mapping is empty
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    fn region(&self) -> *mut c_void {
+        self.mmap.wrapping_sub(self.delta).cast::<c_void>()
+    }
+
+    fn byte_ptr(&self, offset: usize) -> Result<*mut u8, MapError> {
+        checked_subrange(offset, 1, self.len)?;
+
+        Ok(self.mmap.wrapping_add(offset))
+    }
+
+    fn end_access(&self) -> Result<(), MapError> {
+        if self.sync {
+            self.buf.end_cpu_access(self.access)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Ends the CPU access, and unmaps the buffer
+    ///
+    /// Dropping the mapping does the same, but can only log failures. This allows to make sure
+    /// that the final cache synchronization succeeded.
+    ///
+    /// # Errors
+    ///
+    /// Will return an error if either ending the CPU access or the munmap call fails. The buffer
+    /// is unmapped in both cases.
+    pub fn release(self) -> Result<(), MapError> {
+        let this = ManuallyDrop::new(self);
+
+        let ended = this.end_access();
+
+        // SAFETY: It's not clear what rustix expects from a safety perspective, but our pointer is
+        // valid, and is a void pointer at least. this is never used nor dropped afterwards.
+        let unmapped =
+            unsafe { munmap(this.region(), this.mapped_len) }.map_err(MapError::mapping_failed);
+
+        ended.and(unmapped)
+    }
+}
+
+/// Functions of the mappings the CPU can read, [`MappedDmaBufRo`] and [`MappedDmaBufRw`]
+impl<M: Readable> Mapping<'_, M> {
+    /// Returns the content of the buffer
+    #[must_use]
+    pub fn as_slice(&self) -> &[u8] {
+        // SAFETY: We know that the pointer is valid, and the buffer length is at least equal to
+        // self.len bytes. A read-only mapping only ever hands out shared references, and the
+        // writable mappings need an exclusive borrow of the buffer, except the one returned by
+        // DmaBuf::split_access, whose caller guarantees that it's not written to while this slice
+        // is alive. A read-write mapping is the only one of the buffer that can be written to, and
+        // the borrow checker prevents any mutation through it while this slice is alive, so we're
+        // safe.
+        unsafe { slice::from_raw_parts(self.mmap, self.len) }
+    }
+
+    /// Returns the content of the buffer as a slice of `T`
+    ///
+    /// # Errors
+    ///
+    /// Will return [`MapError::InvalidCast`] if the mapping address isn't aligned for `T`, or if
+    /// its length isn't a multiple of the size of `T`.
+    #[cfg(feature = "bytemuck")]
+    pub fn as_slice_of<T: bytemuck::Pod>(&self) -> Result<&[T], MapError> {
+        bytemuck::try_cast_slice(self.as_slice()).map_err(cast_error::<T>)
+    }
+
+    /// Copies the content of the buffer starting at `offset` into `buf`
+    ///
+    /// Copies as many bytes as `buf` can hold, or as there are left in the buffer past `offset`,
+    /// whichever is smaller.
+    ///
+    /// # Errors
+    ///
+    /// Will return [`MapError::RangeOutOfBounds`] if `offset` is past the end of the mapping.
+    pub fn read_at(&self, offset: usize, buf: &mut [u8]) -> Result<usize, MapError> {
+        copy_from_offset(self.as_slice(), offset, buf)
+    }
+
+    /// Copies a `T` out of the buffer, starting at `offset`
+    ///
+    /// The value is copied, so `offset` doesn't need to be aligned for `T`.
+    ///
+    /// # Errors
+    ///
+    /// Will return [`MapError::RangeOutOfBounds`] if the value doesn't fit in the mapping past
+    /// `offset`.
+    #[cfg(feature = "bytemuck")]
+    pub fn read_struct<T: bytemuck::Pod>(&self, offset: usize) -> Result<T, MapError> {
+        let range = checked_subrange(offset, size_of::<T>(), self.len)?;
+
+        Ok(bytemuck::pod_read_unaligned(&self.as_slice()[range]))
+    }
+
+    /// Faults in all the pages of the mapping
+    ///
+    /// Reads a byte from each page, so that accessing the mapping later doesn't fault. Unlike
+    /// [`DmaBuf::memory_map_ro_populated`] or [`DmaBuf::memory_map_rw_populated`], it works on an
+    /// existing mapping. The pages are only faulted in, not locked in memory, and can still be
+    /// reclaimed later.
+    pub fn prefault(&self) {
+        for offset in page_offsets(self.delta, self.len, page_size()) {
+            // SAFETY: The offset is within the mapping, so the pointer is valid for a read, and u8
+            // has no alignment requirement.
+            unsafe { ptr::read_volatile(self.mmap.wrapping_add(offset)) };
+        }
+    }
+
+    /// Reads the byte at `offset` with a volatile read
+    ///
+    /// Regular reads let the compiler assume that the memory doesn't change behind its back, and
+    /// thus merge or elide them. This isn't a concern for the content synchronized when the CPU
+    /// access started, but a device updating the buffer while it's mapped, for example a status
+    /// byte in a coherent buffer, must be polled with volatile reads to observe the updates.
+    /// Volatile reads don't synchronize the caches though, and the updates are only visible
+    /// without further synchronization in buffers coherent with the CPU caches.
+    ///
+    /// # Errors
+    ///
+    /// Will return [`MapError::RangeOutOfBounds`] if `offset` is past the end of the mapping.
+    pub fn read_volatile_at(&self, offset: usize) -> Result<u8, MapError> {
+        let byte = self.byte_ptr(offset)?;
+
+        // SAFETY: byte_ptr() made sure the pointer is within the mapping, which is valid for as
+        // long as we exist, and u8 has no alignment requirement.
+        Ok(unsafe { ptr::read_volatile(byte) })
+    }
+}
+
+/// A `DmaBuf` mapped in memory for the CPU to read it
+///
+/// The CPU read access to the buffer starts when it's mapped, and ends when the `MappedDmaBufRo`
+/// is dropped.
+///
+/// It also implements [`std::io::Read`] and [`std::io::Seek`] through an internal cursor, starting
+/// at the beginning of the buffer. Since the caches have been synchronized when the buffer was
+/// mapped, reads are simple copies from the mapping.
+///
+/// Since it only ever gives a read-only view of the buffer, it can be sent to and shared between
+/// threads to process the buffer content in parallel. The writable mappings can't.
+pub type MappedDmaBufRo<'a> = Mapping<'a, Ro>;
+
+impl MappedDmaBufRo<'_> {
+    /// Computes the CRC32 checksum of the content of the buffer
+    ///
+    /// The caches have been synchronized when the buffer was mapped, so this doesn't need any
+    /// further synchronization.
+    #[cfg(feature = "crc32fast")]
+    #[must_use]
+    pub fn crc32(&self) -> u32 {
+        crc32fast::hash(self.as_slice())
+    }
+
+    /// Returns an iterator over the content of the buffer, one page at a time
+    ///
+    /// Each chunk is the part of a page of the buffer covered by the mapping: the first one starts
+    /// at the offset the mapping was created at, and the last one holds whatever is left past the
+    /// last full page.
+    pub fn pages(&self) -> impl Iterator<Item = &[u8]> {
+        let page = page_size();
+        let (first, rest) = self.as_slice().split_at(self.len.min(page - self.delta));
+
+        (!first.is_empty())
+            .then_some(first)
+            .into_iter()
+            .chain(rest.chunks(page))
+    }
+
+    /// Tells the kernel how the mapping is going to be accessed
+    ///
+    /// This is only a hint, and failures are logged but otherwise ignored. Use
+    /// [`MappedDmaBufRo::try_advise`] to handle them.
+    pub fn advise(&self, advice: Advice) {
+        if let Err(e) = self.try_advise(advice) {
+            warn!("madvise failed: {e}");
+        }
+    }
+
+    /// Tells the kernel how the mapping is going to be accessed
+    ///
+    /// # Errors
+    ///
+    /// Will return [`MapError::MappingFailed`] if the madvise call fails.
+    pub fn try_advise(&self, advice: Advice) -> Result<(), MapError> {
+        // SAFETY: The pointer and length cover our mapping, which stays valid for as long as we
+        // exist. None of the advices we allow change the content of a shared mapping.
+        unsafe { madvise(self.region(), self.mapped_len, advice.into()) }
+            .map_err(MapError::mapping_failed)
+    }
+
+    /// Returns a pointer to the start of the mapping, for FFI consumers
+    ///
+    /// The pointer is valid for [`MappedDmaBufRo::len`] bytes, for as long as the mapping exists.
+    /// The memory must only be read from, and the CPU access ends when the mapping is released or
+    /// dropped.
+    #[must_use]
+    pub fn as_ptr(&self) -> *const u8 {
+        self.mmap
+    }
+}
+
+/// A `DmaBuf` mapped in memory for the CPU to read from and write to it
+///
+/// The CPU read and write access to the buffer starts when it's mapped, and ends when the
+/// `MappedDmaBufRw` is dropped. Copy-on-write mappings created by [`DmaBuf::memory_map_copy`] only
+/// need a CPU read access.
+///
+/// It also implements [`std::io::Write`] and [`std::io::Seek`] through an internal cursor,
+/// starting at the beginning of the buffer. Writes are copied straight into the mapping, and the
+/// caches are synchronized when the mapping is released or dropped.
+pub type MappedDmaBufRw<'a> = Mapping<'a, Rw>;
+
+impl<'a> MappedDmaBufRw<'a> {
+    /// Returns the content of the buffer, for modification
+    #[must_use]
+    pub fn as_slice_mut(&mut self) -> &mut [u8] {
+        // SAFETY: We know that the pointer is valid, and the buffer length is at least equal to
+        // self.len bytes. We hold the only mapping of the buffer, and the borrow checker makes
+        // sure the returned slice is the only reference to it, so we're safe.
+        unsafe { slice::from_raw_parts_mut(self.mmap, self.len) }
+    }
+
+    /// Returns the content of the buffer as a slice of `T`, for modification
+    ///
+    /// # Errors
+    ///
+    /// Will return [`MapError::InvalidCast`] if the mapping address isn't aligned for `T`, or if
+    /// its length isn't a multiple of the size of `T`.
+    #[cfg(feature = "bytemuck")]
+    pub fn as_slice_of_mut<T: bytemuck::Pod>(&mut self) -> Result<&mut [T], MapError> {
+        bytemuck::try_cast_slice_mut(self.as_slice_mut()).map_err(cast_error::<T>)
+    }
+
+    /// Returns the content of the buffer as a slice of atomic bytes
+    ///
+    /// Atomic bytes can be loaded and stored with explicit orderings, and shared between threads,
+    /// which allows to coordinate through the buffer, in a mailbox for example, without assuming
+    /// an exclusive access like `&mut [u8]` does. It takes `&mut self` nonetheless, so that no
+    /// regular slice of the buffer, that assumes the buffer doesn't change, coexists with it.
+    ///
+    /// Atomics only order the accesses of the CPUs between themselves: they don't synchronize the
+    /// caches with the devices, which is still done by the `DMA_BUF_IOCTL_SYNC` ioctls when the
+    /// CPU access starts and ends, and the device accesses are only visible through them right
+    /// away if the buffer is coherent with the CPU caches.
+    #[must_use]
+    pub fn as_atomic_slice(&mut self) -> &[AtomicU8] {
+        // SAFETY: We know that the pointer is valid, and the buffer length is at least equal to
+        // self.len bytes. AtomicU8 has the same size and alignment as u8, and we hold the only
+        // reference to the mapping for as long as the returned slice lives, so all the accesses
+        // to the mapping go through the atomics.
+        unsafe { slice::from_raw_parts(self.mmap.cast::<AtomicU8>(), self.len) }
+    }
+
+    /// Splits the content of the buffer in two mutable slices, at `mid`
+    ///
+    /// The first slice holds the bytes before `mid`, and the second one the rest of the buffer.
+    /// They don't overlap, so they can be modified independently, from different threads for
+    /// example.
+    ///
+    /// # Errors
+    ///
+    /// Will return [`MapError::RangeOutOfBounds`] if `mid` is past the end of the mapping.
+    pub fn split_at_mut(&mut self, mid: usize) -> Result<(&mut [u8], &mut [u8]), MapError> {
+        checked_subrange(mid, 0, self.len)?;
+
+        Ok(self.as_slice_mut().split_at_mut(mid))
+    }
+
+    /// Sets every byte of the buffer to `value`
+    ///
+    /// The CPU write access started when the buffer was mapped, and the caches are synchronized
+    /// when the mapping is released or dropped.
+    pub fn fill(&mut self, value: u8) {
+        self.as_slice_mut().fill(value);
+    }
 
-impl DmaBuf {
-    /// Maps a `DmaBuf` for the CPU to access it
+    /// Sets the `len` bytes of the buffer starting at `offset` to `value`
     ///
-    /// # Panics
+    /// # Errors
+    ///
+    /// Will return [`MapError::RangeOutOfBounds`] if the range doesn't fit in the mapping.
+    pub fn fill_range(&mut self, offset: usize, len: usize, value: u8) -> Result<(), MapError> {
+        let range = checked_subrange(offset, len, self.len)?;
+
+        self.as_slice_mut()[range].fill(value);
+
+        Ok(())
+    }
+
+    /// Copies `data` into the buffer, starting at `offset`
     ///
-    /// If the buffer size reported by the kernel (`i64`) cannot fit into an `usize`.
+    /// Copies as many bytes as `data` holds, or as there are left in the buffer past `offset`,
+    /// whichever is smaller.
     ///
     /// # Errors
     ///
-    /// Will return an error if either the Buffer's length can't be retrieved, or if the mmap call
-    /// fails.
-    pub fn memory_map(self) -> Result<MappedDmaBuf, MapError> {
-        debug!("Mapping DMA-Buf buffer with File Descriptor {:#?}", self.0);
-
-        let stat = fstat(&self.0).map_err(|e| MapError::FdAccess {
-            reason: e.to_string(),
-            source: std::io::Error::from(e),
-        })?;
+    /// Will return [`MapError::RangeOutOfBounds`] if `offset` is past the end of the mapping.
+    pub fn write_at(&mut self, offset: usize, data: &[u8]) -> Result<usize, MapError> {
+        copy_to_offset(self.as_slice_mut(), offset, data)
+    }
 
-        let len = usize::try_from(stat.st_size)?.next_multiple_of(page_size());
-        debug!("Valid buffer, size {len}");
-
-        // SAFETY: It's unclear at this point what the exact safety requirements from mmap are, but
-        // our fd is valid and the length is aligned, so that's something.
-        let mapping_ptr = unsafe {
-            mmap(
-                ptr::null_mut(),
-                len,
-                ProtFlags::READ | ProtFlags::WRITE,
-                MapFlags::SHARED,
-                &self.0,
-                0,
-            )
-        }
-        .map(<*mut c_void>::cast::<u8>)
-        .map_err(|e| MapError::MappingFailed {
-            reason: e.to_string(),
-            source: std::io::Error::from(e),
-        })?;
+    /// Copies `value` into the buffer, starting at `offset`
+    ///
+    /// The value is copied, so `offset` doesn't need to be aligned for `T`.
+    ///
+    /// # Errors
+    ///
+    /// Will return [`MapError::RangeOutOfBounds`] if the value doesn't fit in the mapping past
+    /// `offset`.
+    #[cfg(feature = "bytemuck")]
+    pub fn write_struct<T: bytemuck::Pod>(
+        &mut self,
+        offset: usize,
+        value: T,
+    ) -> Result<(), MapError> {
+        let range = checked_subrange(offset, size_of::<T>(), self.len)?;
 
-        debug!("Memory Mapping Done");
+        self.as_slice_mut()[range].copy_from_slice(bytemuck::bytes_of(&value));
 
-        Ok(MappedDmaBuf {
-            buf: self,
-            len,
-            mmap: mapping_ptr,
-        })
+        Ok(())
     }
-}
 
-/// A `DmaBuf` mapped in memory
-pub struct MappedDmaBuf {
-    buf: DmaBuf,
-    len: usize,
-    mmap: *mut u8,
-}
+    /// Writes the modified pages of the mapping back, without unmapping it
+    ///
+    /// This is an `msync(MS_SYNC)` call over the whole mapping, and is distinct from the cache
+    /// synchronization done through `DMA_BUF_IOCTL_SYNC`: the CPU access is still ongoing after
+    /// this call, and the caches will only be synchronized when the mapping is released or
+    /// dropped. Most exporters don't need it, but it provides an explicit flush point for those
+    /// backed by pages that need to be written back.
+    ///
+    /// It isn't called `flush` to avoid any confusion with [`std::io::Write::flush`], which is a
+    /// no-op.
+    ///
+    /// # Errors
+    ///
+    /// Will return [`MapError::MappingFailed`] if the msync call fails.
+    pub fn msync(&self) -> Result<(), MapError> {
+        // SAFETY: The pointer and length cover our mapping, which stays valid for as long as we
+        // exist.
+        unsafe { msync(self.region(), self.mapped_len, MsyncFlags::SYNC) }
+            .map_err(MapError::mapping_failed)
+    }
 
-/// Error type to access a [`MappedDmaBuf`]
-#[derive(Debug, thiserror::Error)]
-pub enum BufferError {
-    /// An Error occured while accessing the buffer file descriptor
-    #[error("Could not access the buffer: {reason}")]
-    FdAccess {
-        /// Description of the Error
-        reason: String,
+    /// Faults in all the pages of the mapping for writing
+    ///
+    /// Unlike [`Mapping::prefault`], it writes back a byte to each page, without changing
+    /// its value, so that the pages are also faulted in as writable and dirtied.
+    pub fn prefault_write(&mut self) {
+        for offset in page_offsets(self.delta, self.len, page_size()) {
+            let byte = self.mmap.wrapping_add(offset);
 
-        /// Source of the Error
-        source: std::io::Error,
-    },
+            // SAFETY: The offset is within the mapping, so the pointer is valid for a read, and u8
+            // has no alignment requirement.
+            let value = unsafe { ptr::read_volatile(byte) };
 
-    /// An Error occured in the closure
-    #[error("The closure returned an error: {0}")]
-    Closure(Box<dyn std::error::Error>),
-}
+            // SAFETY: The pointer is valid for a write for the same reasons, and we hold the only
+            // reference to the mapping.
+            unsafe { ptr::write_volatile(byte, value) };
+        }
+    }
 
-impl MappedDmaBuf {
-    fn as_slice(&self) -> &[u8] {
-        // SAFETY: We know that the pointer is valid, and the buffer length is at least equal to
-        // self.len bytes. The backing buffer won't be mutated by the kernel, our structure is the
-        // sole owner of the pointer, and it won't be mutated in our code either, so we're safe.
-        unsafe { slice::from_raw_parts(self.mmap, self.len) }
+    /// Locks the pages of the mapping in memory
+    ///
+    /// The pages are faulted in if needed, and can't be reclaimed until [`MappedDmaBufRw::unlock`]
+    /// is called, or the buffer is unmapped when the mapping is released or dropped.
+    ///
+    /// # Errors
+    ///
+    /// Will return [`MapError::MappingFailed`] if the mlock call fails, for example with `ENOMEM`
+    /// if the `RLIMIT_MEMLOCK` limit would be exceeded.
+    pub fn lock(&self) -> Result<(), MapError> {
+        // SAFETY: The pointer and length cover our mapping, which stays valid for as long as we
+        // exist.
+        unsafe { mlock(self.region(), self.mapped_len) }.map_err(MapError::mapping_failed)
     }
 
-    fn as_slice_mut(&mut self) -> &mut [u8] {
-        // SAFETY: We know that the pointer is valid, and the buffer length is at least equal to
-        // self.len bytes. The backing buffer won't be mutated by the kernel, our structure is the
-        // sole owner of the pointer, and it won't be mutated in our code either, so we're safe.
-        unsafe { slice::from_raw_parts_mut(self.mmap, self.len) }
+    /// Unlocks the pages of the mapping locked by [`MappedDmaBufRw::lock`]
+    ///
+    /// # Errors
+    ///
+    /// Will return [`MapError::MappingFailed`] if the munlock call fails.
+    pub fn unlock(&self) -> Result<(), MapError> {
+        // SAFETY: The pointer and length cover our mapping, which stays valid for as long as we
+        // exist.
+        unsafe { munlock(self.region(), self.mapped_len) }.map_err(MapError::mapping_failed)
     }
 
-    /// Calls a closure to read the buffer content
+    /// Returns a mutable pointer to the start of the mapping, for FFI consumers
     ///
-    /// DMA-Buf requires the user-space to call the `DMA_BUF_IOCTL_SYNC` ioctl before and after any
-    /// CPU access to a buffer in order to maintain the cache coherency. The closure will be run
-    /// with those primitives called for a read access from the CPU.
+    /// The pointer is valid for [`MappedDmaBufRw::len`] bytes, for as long as the mapping exists.
+    /// The caches are synchronized when the mapping is released or dropped.
+    #[must_use]
+    pub fn as_mut_ptr(&mut self) -> *mut u8 {
+        self.mmap
+    }
+
+    /// Writes `value` at `offset` with a volatile write
     ///
-    /// The result of the closure will be returned.
+    /// Volatile writes can't be merged or elided by the compiler, which matters for the registers
+    /// or mailboxes a device polls while the buffer is mapped. Like the reads, they don't
+    /// synchronize the caches, and are only visible right away in buffers coherent with the CPU
+    /// caches. See [`Mapping::read_volatile_at`].
     ///
     /// # Errors
     ///
-    /// Will return [Error] if the underlying ioctl or the closure fails
-    pub fn read<A, F, R>(&self, f: F, arg: Option<A>) -> Result<R, BufferError>
-    where
-        F: Fn(&[u8], Option<A>) -> Result<R, Box<dyn std::error::Error>>,
-    {
-        debug!("Preparing the buffer for read access");
+    /// Will return [`MapError::RangeOutOfBounds`] if `offset` is past the end of the mapping.
+    pub fn write_volatile_at(&mut self, offset: usize, value: u8) -> Result<(), MapError> {
+        let byte = self.byte_ptr(offset)?;
 
-        dma_buf_begin_cpu_read_access(self.buf.as_fd())?;
+        // SAFETY: byte_ptr() made sure the pointer is within the mapping, which is valid for as
+        // long as we exist, we hold the only reference to it, and u8 has no alignment requirement.
+        unsafe { ptr::write_volatile(byte, value) };
 
-        debug!("Accessing the buffer");
+        Ok(())
+    }
 
-        let ret = {
-            let bytes = self.as_slice();
+    /// Converts the mapping into a read-only one, without remapping the buffer
+    ///
+    /// The `DMA_BUF_IOCTL_SYNC` flags are per direction, so only the write access is ended, which
+    /// flushes the CPU writes out of the caches, while the read access started along with it is
+    /// kept, and ended when the returned mapping is released or dropped. This avoids both a new
+    /// mmap call and the wait for the devices the start of a new read access would need.
+    ///
+    /// Failing to end the write access can only be logged, and the conversion happens
+    /// nonetheless.
+    #[must_use]
+    pub fn into_ro(self) -> MappedDmaBufRo<'a> {
+        let this = ManuallyDrop::new(self);
 
-            f(bytes, arg)
-                .map(|v| {
-                    debug!("Closure done without error");
-                    v
-                })
-                .map_err(|e| {
-                    debug!("Closure encountered an error {}", e);
-                    BufferError::Closure(e)
-                })
-        };
+        if this.sync
+            && this.access == SyncMode::ReadWrite
+            && this.buf.end_cpu_access(SyncMode::Write).is_err()
+        {
+            warn!("Ending the CPU write access failed!");
+        }
 
-        dma_buf_end_cpu_read_access(self.buf.as_fd())?;
+        Mapping {
+            buf: this.buf,
+            len: this.len,
+            mapped_len: this.mapped_len,
+            mmap: this.mmap,
+            delta: this.delta,
+            pos: this.pos,
+            sync: this.sync,
+            access: SyncMode::Read,
+            mode: PhantomData,
+        }
+    }
+}
 
-        debug!("Buffer access done");
+/// A `DmaBuf` mapped in memory for the CPU to write to it
+///
+/// The CPU write access to the buffer starts when it's mapped, and ends when the `MappedDmaBufWo`
+/// is dropped.
+///
+/// Since the caches aren't synchronized for reading, it doesn't implement [`Deref`], and thus
+/// can't implement [`DerefMut`] either.
+///
+/// It implements [`std::io::Write`] and [`std::io::Seek`] through an internal cursor, starting at
+/// the beginning of the buffer. Writes are copied straight into the mapping, and the caches are
+/// synchronized when the mapping is released or dropped.
+pub type MappedDmaBufWo<'a> = Mapping<'a, Wo>;
 
-        ret
+impl MappedDmaBufWo<'_> {
+    /// Returns the content of the buffer, for modification
+    ///
+    /// The caches are only synchronized for a write access, so the content of the buffer can't be
+    /// read reliably. The returned slice is thus made of [`MaybeUninit`] bytes, which can be
+    /// written to but not read from without unsafe code.
+    #[must_use]
+    pub fn as_slice_mut(&mut self) -> &mut [MaybeUninit<u8>] {
+        // SAFETY: We know that the pointer is valid, and the buffer length is at least equal to
+        // self.len bytes. We hold the only mapping of the buffer, and the borrow checker makes
+        // sure the returned slice is the only reference to it, so we're safe. MaybeUninit<u8> has
+        // the same layout as u8, and doesn't require its content to be initialized.
+        unsafe { slice::from_raw_parts_mut(self.mmap.cast::<MaybeUninit<u8>>(), self.len) }
     }
 
-    /// Calls a closure to read from and write to the buffer content
-    ///
-    /// DMA-Buf requires the user-space to call the `DMA_BUF_IOCTL_SYNC` ioctl before and after any
-    /// CPU access to a buffer in order to maintain the cache coherency. The closure will be run
-    /// with those primitives called for a read and write access from the CPU.
-    ///
-    /// The result of the closure will be returned on success. On failure, the closure must return
-    /// `Error::Closure`
+    /// Copies `data` at the start of the buffer
     ///
     /// # Errors
     ///
-    /// Will return [Error] if the underlying ioctl or the closure fails
-    pub fn readwrite<A, F, R>(&mut self, f: F, arg: Option<A>) -> Result<R, BufferError>
-    where
-        F: Fn(&mut [u8], Option<A>) -> Result<R, Box<dyn std::error::Error>>,
-    {
-        debug!("Preparing the buffer for read/write access");
-
-        dma_buf_begin_cpu_readwrite_access(self.buf.as_fd())?;
-
-        debug!("Accessing the buffer");
+    /// Will return [`MapError::RangeOutOfBounds`] if `data` is larger than the mapping.
+    pub fn copy_from_slice(&mut self, data: &[u8]) -> Result<(), MapError> {
+        let range = checked_subrange(0, data.len(), self.len)?;
 
-        let ret = {
-            let bytes = self.as_slice_mut();
+        copy_to_uninit(&mut self.as_slice_mut()[range], data);
 
-            f(bytes, arg)
-                .map(|v| {
-                    debug!("Closure done without error");
-                    v
-                })
-                .map_err(|e| {
-                    debug!("Closure encountered an error {}", e);
-                    BufferError::Closure(e)
-                })
-        };
+        Ok(())
+    }
 
-        dma_buf_end_cpu_readwrite_access(self.buf.as_fd())?;
+    /// Sets every byte of the buffer to `value`
+    ///
+    /// This only writes to the mapping, so it's safe to use without any read cache
+    /// synchronization. The CPU write access started when the buffer was mapped, and the caches
+    /// are synchronized when the mapping is released or dropped.
+    pub fn fill(&mut self, value: u8) {
+        self.as_slice_mut().fill(MaybeUninit::new(value));
+    }
 
-        debug!("Buffer access done");
+    /// Copies `data` into the buffer, starting at `offset`
+    ///
+    /// Copies as many bytes as `data` holds, or as there are left in the buffer past `offset`,
+    /// whichever is smaller.
+    ///
+    /// # Errors
+    ///
+    /// Will return [`MapError::RangeOutOfBounds`] if `offset` is past the end of the mapping.
+    pub fn write_at(&mut self, offset: usize, data: &[u8]) -> Result<usize, MapError> {
+        let start = checked_subrange(offset, 0, self.len)?.start;
 
-        ret
+        Ok(copy_to_uninit(&mut self.as_slice_mut()[start..], data))
     }
 
-    /// Calls a closure to read from and write to the buffer content
+    /// Returns a mutable pointer to the start of the mapping, for FFI consumers
     ///
-    /// DMA-Buf requires the user-space to call the `DMA_BUF_IOCTL_SYNC` ioctl before and after any
-    /// CPU access to a buffer in order to maintain the cache coherency. The closure will be run
-    /// with those primitives called for a read and write access from the CPU.
+    /// The pointer is valid for [`MappedDmaBufWo::len`] bytes, for as long as the mapping exists.
+    /// The memory must only be written to, since it isn't synchronized for reading, and the
+    /// caches are synchronized when the mapping is released or dropped.
+    #[must_use]
+    pub fn as_mut_ptr(&mut self) -> *mut u8 {
+        self.mmap
+    }
+
+    /// Writes `value` at `offset` with a volatile write
     ///
-    /// The closure must return () on success. On failure, the closure must return `Error::Closure`.
+    /// See [`MappedDmaBufRw::write_volatile_at`].
     ///
     /// # Errors
     ///
-    /// Will return [Error] if the underlying ioctl or the closure fails
-    pub fn write<A, F>(&mut self, f: F, arg: Option<A>) -> Result<(), BufferError>
-    where
-        F: Fn(&mut [u8], Option<A>) -> Result<(), Box<dyn std::error::Error>>,
-    {
-        debug!("Preparing the buffer for write access");
+    /// Will return [`MapError::RangeOutOfBounds`] if `offset` is past the end of the mapping.
+    pub fn write_volatile_at(&mut self, offset: usize, value: u8) -> Result<(), MapError> {
+        let byte = self.byte_ptr(offset)?;
 
-        dma_buf_begin_cpu_write_access(self.buf.as_fd())?;
+        // SAFETY: byte_ptr() made sure the pointer is within the mapping, which is valid for as
+        // long as we exist, we hold the only reference to it, and u8 has no alignment requirement.
+        unsafe { ptr::write_volatile(byte, value) };
 
-        debug!("Accessing the buffer");
+        Ok(())
+    }
+}
 
-        let ret = {
-            let bytes = self.as_slice_mut();
+impl From<OwnedFd> for DmaBuf {
+    fn from(owned: OwnedFd) -> Self {
+        Self {
+            fd: owned,
+            len: AtomicUsize::new(0),
+        }
+    }
+}
 
-            f(bytes, arg)
-                .map(|()| {
-                    debug!("Closure done without error");
-                })
-                .map_err(|e| {
-                    debug!("Closure encountered an error {}", e);
-                    BufferError::Closure(e)
-                })
-        };
+/// Takes over the file descriptor of the `File`
+///
+/// The mode the file was opened with sets the mappings that will succeed: a file opened read-only
+/// can only be mapped with [`DmaBuf::memory_map_ro`]. See [`DmaBuf::access_mode`].
+#[cfg(feature = "std")]
+impl From<std::fs::File> for DmaBuf {
+    fn from(file: std::fs::File) -> Self {
+        Self::from(OwnedFd::from(file))
+    }
+}
 
-        dma_buf_end_cpu_write_access(self.buf.as_fd())?;
+#[cfg(feature = "std")]
+impl From<MapError> for std::io::Error {
+    fn from(e: MapError) -> Self {
+        use std::io::ErrorKind;
 
-        debug!("Buffer access done");
+        let kind = match e {
+            MapError::FdAccess { source, .. }
+            | MapError::MappingFailed { source, .. }
+            | MapError::OutOfMemory { source, .. }
+            | MapError::Io(source) => return source,
+            MapError::Batch { source, .. } => return Self::from(*source),
+            MapError::NotDmaBuf
+            | MapError::TooLarge { .. }
+            | MapError::EmptyBuffer
+            | MapError::UnalignedSize { .. }
+            | MapError::UnalignedAddress { .. }
+            | MapError::RangeOutOfBounds { .. }
+            | MapError::IntegerConversionFailed(_)
+            | MapError::InvalidCast { .. }
+            | MapError::InvalidName { .. } => ErrorKind::InvalidInput,
+            MapError::Unsupported { .. } => ErrorKind::Unsupported,
+            MapError::Busy => ErrorKind::ResourceBusy,
+            MapError::Timeout(_) => ErrorKind::TimedOut,
+            MapError::Closure(_) => ErrorKind::Other,
+        };
 
-        ret
+        Self::new(kind, e)
     }
 }
 
-impl From<OwnedFd> for DmaBuf {
-    fn from(owned: OwnedFd) -> Self {
-        Self(owned)
+impl From<DmaBuf> for OwnedFd {
+    fn from(buf: DmaBuf) -> Self {
+        buf.fd
     }
 }
 
 impl AsFd for DmaBuf {
     fn as_fd(&self) -> BorrowedFd<'_> {
-        self.0.as_fd()
+        self.fd.as_fd()
     }
 }
 
 impl AsRawFd for DmaBuf {
     fn as_raw_fd(&self) -> RawFd {
-        self.0.as_raw_fd()
+        self.fd.as_raw_fd()
     }
 }
 
@@ -319,13 +2526,171 @@ impl AsRawFd for MappedDmaBuf {
     }
 }
 
+impl<M: Access> AsFd for Mapping<'_, M> {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.buf.as_fd()
+    }
+}
+
+impl<M: Access> AsRawFd for Mapping<'_, M> {
+    fn as_raw_fd(&self) -> RawFd {
+        self.buf.as_raw_fd()
+    }
+}
+
+// SAFETY: The raw pointer is the only thing preventing MappedDmaBufRo from being Send. It points
+// to a shared mapping that is only valid for as long as MappedDmaBufRo exists, and no other
+// reference to it exists outside of the borrows we hand out. Ending the CPU access and unmapping
+// the buffer can be done from any thread.
+unsafe impl Send for MappedDmaBufRo<'_> {}
+
+// SAFETY: The mapping is read-only, and we only ever hand out shared references to its content
+// through &self. The buffer isn't written to while those are alive, see
+// MappedDmaBufRo::as_slice, so sharing a MappedDmaBufRo across threads is the same as sharing a
+// &[u8].
+unsafe impl Sync for MappedDmaBufRo<'_> {}
+
+impl Deref for MappedDmaBufRo<'_> {
+    type Target = [u8];
+
+    fn deref(&self) -> &Self::Target {
+        self.as_slice()
+    }
+}
+
+impl Deref for MappedDmaBufRw<'_> {
+    type Target = [u8];
+
+    fn deref(&self) -> &Self::Target {
+        self.as_slice()
+    }
+}
+
+impl DerefMut for MappedDmaBufRw<'_> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.as_slice_mut()
+    }
+}
+
+/// Indexes the content of the buffer, like a slice
+///
+/// # Panics
+///
+/// Just like slices, it panics if the index is out of the bounds of the mapping.
+impl<I: SliceIndex<[u8]>> Index<I> for MappedDmaBufRo<'_> {
+    type Output = I::Output;
+
+    fn index(&self, index: I) -> &Self::Output {
+        &self.as_slice()[index]
+    }
+}
+
+/// Indexes the content of the buffer, like a slice
+///
+/// # Panics
+///
+/// Just like slices, it panics if the index is out of the bounds of the mapping.
+impl<I: SliceIndex<[u8]>> Index<I> for MappedDmaBufRw<'_> {
+    type Output = I::Output;
+
+    fn index(&self, index: I) -> &Self::Output {
+        &self.as_slice()[index]
+    }
+}
+
+/// Indexes the content of the buffer for modification, like a slice
+///
+/// # Panics
+///
+/// Just like slices, it panics if the index is out of the bounds of the mapping.
+impl<I: SliceIndex<[u8]>> IndexMut<I> for MappedDmaBufRw<'_> {
+    fn index_mut(&mut self, index: I) -> &mut Self::Output {
+        &mut self.as_slice_mut()[index]
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::io::Read for MappedDmaBufRo<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let remaining = self.as_slice().get(self.pos..).unwrap_or_default();
+        let count = remaining.len().min(buf.len());
+
+        buf[..count].copy_from_slice(&remaining[..count]);
+        self.pos += count;
+
+        Ok(count)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<M: Access> std::io::Seek for Mapping<'_, M> {
+    fn seek(&mut self, pos: std::io::SeekFrom) -> std::io::Result<u64> {
+        let new = seek_position(self.pos, self.len, pos)?;
+        self.pos = usize::try_from(new).map_err(std::io::Error::other)?;
+
+        Ok(new)
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::io::Write for MappedDmaBufRw<'_> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let pos = self.pos;
+        let remaining = self.as_slice_mut().get_mut(pos..).unwrap_or_default();
+        let count = remaining.len().min(buf.len());
+
+        remaining[..count].copy_from_slice(&buf[..count]);
+        self.pos += count;
+
+        Ok(count)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::io::Write for MappedDmaBufWo<'_> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let pos = self.pos;
+        let remaining = self.as_slice_mut().get_mut(pos..).unwrap_or_default();
+        let count = copy_to_uninit(remaining, buf);
+
+        self.pos += count;
+
+        Ok(count)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
 impl FromRawFd for DmaBuf {
     unsafe fn from_raw_fd(fd: RawFd) -> Self {
-        debug!("Importing DMABuf from File Descriptor {}", fd);
+        debug!("Importing DMABuf from File Descriptor {fd}");
 
         // SAFETY: We're just forwarding the FromRawFd implementation to our inner OwnerFd type.
         // We're having exactly the same safety guarantees.
-        Self(unsafe { OwnedFd::from_raw_fd(fd) })
+        Self::from(unsafe { OwnedFd::from_raw_fd(fd) })
+    }
+}
+
+impl IntoRawFd for DmaBuf {
+    fn into_raw_fd(self) -> RawFd {
+        self.fd.into_raw_fd()
+    }
+}
+
+impl fmt::Display for DmaBuf {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // We don't want to issue a syscall that could fail while formatting, so we only display
+        // the size if it's been cached already.
+        match self.len.load(Ordering::Relaxed) {
+            0 => write!(f, "DmaBuf(fd={})", self.as_raw_fd()),
+            len => write!(f, "DmaBuf(fd={}, {len} bytes)", self.as_raw_fd()),
+        }
     }
 }
 
@@ -334,6 +2699,7 @@ impl fmt::Debug for MappedDmaBuf {
         f.debug_struct("MappedDmaBuf")
             .field("DmaBuf", &self.buf)
             .field("len", &self.len)
+            .field("mapped_len", &self.mapped_len)
             .field("address", &self.mmap)
             .finish()
     }
@@ -343,8 +2709,37 @@ impl Drop for MappedDmaBuf {
     fn drop(&mut self) {
         // SAFETY: It's not clear what rustix expects from a safety perspective, but our pointer is
         // valid, and is a void pointer at least.
-        if unsafe { munmap(self.mmap.cast::<c_void>(), self.len) }.is_err() {
+        if unsafe { munmap(self.mmap.cast::<c_void>(), self.mapped_len) }.is_err() {
+            warn!("unmap failed!");
+        }
+    }
+}
+
+impl<M: Access> fmt::Debug for Mapping<'_, M> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Mapping")
+            .field("DmaBuf", &self.buf)
+            .field("access", &self.access)
+            .field("len", &self.len)
+            .field("mapped_len", &self.mapped_len)
+            .field("address", &self.mmap)
+            .finish()
+    }
+}
+
+impl<M: Access> Drop for Mapping<'_, M> {
+    fn drop(&mut self) {
+        if self.end_access().is_err() {
+            warn!("Ending the CPU access failed!");
+        }
+
+        // SAFETY: It's not clear what rustix expects from a safety perspective, but our pointer is
+        // valid, and is a void pointer at least.
+        if unsafe { munmap(self.region(), self.mapped_len) }.is_err() {
             warn!("unmap failed!");
         }
     }
 }
+
+#[cfg(test)]
+mod tests;
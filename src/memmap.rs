@@ -0,0 +1,119 @@
+// Copyright 2020-2021, Cerno
+// Licensed under the MIT License
+// See the LICENSE file or <http://opensource.org/licenses/MIT>
+
+use core::ops::Deref;
+
+use log::warn;
+use memmap2::{Mmap, MmapMut, MmapOptions};
+use rustix::io::Errno;
+
+use crate::{DmaBuf, MapError, SyncMode};
+
+fn memmap_error(e: std::io::Error) -> MapError {
+    MapError::MappingFailed {
+        reason: e.to_string(),
+        errno: e.raw_os_error().map(Errno::from_raw_os_error),
+        source: e,
+    }
+}
+
+/// A [`DmaBuf`] mapped through [`memmap2`] for the CPU to read it
+///
+/// It dereferences to a [`memmap2::Mmap`], for code that is generic over `memmap2` mappings. The
+/// mapping is owned by the `Mmap`, while the `MemmapRo` handles the CPU access: it starts when
+/// the buffer is mapped, and ends when the `MemmapRo` is dropped, right before the `Mmap` unmaps
+/// the buffer. The `Mmap` thus can't be moved out of it.
+#[derive(Debug)]
+pub struct MemmapRo<'a> {
+    buf: &'a DmaBuf,
+    mmap: Mmap,
+}
+
+impl<'a> TryFrom<&'a DmaBuf> for MemmapRo<'a> {
+    type Error = MapError;
+
+    fn try_from(buf: &'a DmaBuf) -> Result<Self, Self::Error> {
+        let len = buf.len()?;
+
+        // SAFETY: memmap2 considers mapping a file unsafe because it could be modified while
+        // mapped. The CPU access we start right after mapping it makes sure the devices are done
+        // writing to the buffer, which is as much as we can do, just like our own mappings.
+        let mmap = unsafe { MmapOptions::new().len(len).map(buf) }.map_err(memmap_error)?;
+
+        buf.begin_cpu_access(SyncMode::Read)?;
+
+        Ok(Self { buf, mmap })
+    }
+}
+
+impl Deref for MemmapRo<'_> {
+    type Target = Mmap;
+
+    fn deref(&self) -> &Self::Target {
+        &self.mmap
+    }
+}
+
+impl Drop for MemmapRo<'_> {
+    fn drop(&mut self) {
+        if self.buf.end_cpu_access(SyncMode::Read).is_err() {
+            warn!("Ending the CPU access failed!");
+        }
+    }
+}
+
+/// A [`DmaBuf`] mapped through [`memmap2`] for the CPU to read from and write to it
+///
+/// It dereferences to a [`memmap2::MmapMut`], for code that is generic over `memmap2` mappings.
+/// See [`MemmapRo`] for the lifetime of the CPU access.
+///
+/// Only a shared reference to the `MmapMut` is given out, since a mutable one would allow to swap
+/// it for another mapping, or to take it out of the `MemmapRw` before the CPU access ends. The
+/// data can be modified through [`MemmapRw::as_slice_mut`] instead.
+#[derive(Debug)]
+pub struct MemmapRw<'a> {
+    buf: &'a DmaBuf,
+    mmap: MmapMut,
+}
+
+impl<'a> TryFrom<&'a mut DmaBuf> for MemmapRw<'a> {
+    type Error = MapError;
+
+    fn try_from(buf: &'a mut DmaBuf) -> Result<Self, Self::Error> {
+        let buf = &*buf;
+        let len = buf.len()?;
+
+        // SAFETY: memmap2 considers mapping a file unsafe because it could be modified while
+        // mapped. The CPU access we start right after mapping it makes sure the devices are done
+        // accessing the buffer, which is as much as we can do, just like our own mappings.
+        let mmap = unsafe { MmapOptions::new().len(len).map_mut(buf) }.map_err(memmap_error)?;
+
+        buf.begin_cpu_access(SyncMode::ReadWrite)?;
+
+        Ok(Self { buf, mmap })
+    }
+}
+
+impl Deref for MemmapRw<'_> {
+    type Target = MmapMut;
+
+    fn deref(&self) -> &Self::Target {
+        &self.mmap
+    }
+}
+
+impl MemmapRw<'_> {
+    /// Returns the mapped buffer as a mutable slice
+    pub fn as_slice_mut(&mut self) -> &mut [u8] {
+        &mut self.mmap
+    }
+}
+
+impl Drop for MemmapRw<'_> {
+    fn drop(&mut self) {
+        if self.buf.end_cpu_access(SyncMode::ReadWrite).is_err() {
+            warn!("Ending the CPU access failed!");
+        }
+    }
+}
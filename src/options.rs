@@ -0,0 +1,350 @@
+// Copyright 2020-2021, Cerno
+// Licensed under the MIT License
+// See the LICENSE file or <http://opensource.org/licenses/MIT>
+
+use core::{ffi::c_void, num::NonZeroUsize, ptr};
+
+use log::warn;
+use rustix::{
+    fd::{AsFd, BorrowedFd},
+    mm::{munmap, MapFlags, ProtFlags},
+    param::page_size,
+};
+
+use crate::{
+    ioctl::{
+        dma_buf_begin_cpu_read_access, dma_buf_begin_cpu_readwrite_access,
+        dma_buf_begin_cpu_write_access,
+    },
+    mapping_layout, DmaBuf, Layout, MapError, MappedDmaBufRo, MappedDmaBufRw, MappedDmaBufWo,
+    Mapping, SyncMode, MMAP_RETRIES,
+};
+
+/// Configuration of a [`DmaBuf`] mapping
+///
+/// A `MapOptions` collects the settings of a mapping, and creates it through [`MapOptions::map`],
+/// [`MapOptions::map_mut`], [`MapOptions::map_wo`] or [`MapOptions::map_copy`] depending on the
+/// access needed. The `DmaBuf::memory_map_*` functions are shortcuts for the common cases.
+///
+/// By default, the whole buffer is mapped, shared, without prefaulting, and with the cache
+/// synchronized when the CPU access starts and ends.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MapOptions {
+    offset: usize,
+    len: Option<usize>,
+    buffer_len: Option<usize>,
+    populate: bool,
+    skip_sync: bool,
+    address: Option<NonZeroUsize>,
+    placement: Placement,
+    mmap_retries: Option<u32>,
+}
+
+/// How the kernel must treat the requested mapping address
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+enum Placement {
+    /// The address is only a hint
+    #[default]
+    Hint,
+
+    /// The mapping must be at the address, if it doesn't overlap an existing mapping
+    NoReplace,
+
+    /// The mapping must be at the address, replacing any existing mapping
+    Fixed,
+}
+
+impl MapOptions {
+    /// Creates a new `MapOptions` with the default settings
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the offset in the buffer to start the mapping at
+    ///
+    /// It doesn't need to be aligned: the mapping covers the whole pages the range spans, but the
+    /// accessors only give access to the range itself. Defaults to 0.
+    #[must_use]
+    pub fn offset(mut self, offset: usize) -> Self {
+        self.offset = offset;
+        self
+    }
+
+    /// Sets the number of bytes to map
+    ///
+    /// Defaults to the rest of the buffer past the offset.
+    #[must_use]
+    pub fn len(mut self, len: usize) -> Self {
+        self.len = Some(len);
+        self
+    }
+
+    /// Overrides the size of the buffer
+    ///
+    /// The size of the buffer is otherwise retrieved from the kernel, but some exporters report
+    /// a wrong size, such as 0 or the size of the blocks the buffer was allocated from. The mapping
+    /// still gets rounded up to a multiple of the page size.
+    ///
+    /// # Safety
+    ///
+    /// The accesses to the mapping and their bounds checks are done according to this size, and
+    /// not every exporter refuses to map past the end of the buffer: accessing the mapping past
+    /// the end of the memory backing the buffer raises a `SIGBUS`. `len` must thus not be larger
+    /// than the actual size of the buffer.
+    #[must_use]
+    pub unsafe fn buffer_len(mut self, len: usize) -> Self {
+        self.buffer_len = Some(len);
+        self
+    }
+
+    /// Prefaults the mapping with `MAP_POPULATE`
+    ///
+    /// See [`DmaBuf::memory_map_ro_populated`] for the trade-offs. Defaults to false.
+    #[must_use]
+    pub fn populate(mut self, populate: bool) -> Self {
+        self.populate = populate;
+        self
+    }
+
+    /// Skips the cache synchronization when the CPU access starts and ends
+    ///
+    /// The `DMA_BUF_IOCTL_SYNC` ioctls are pure overhead for buffers that are coherent with the
+    /// CPU caches, such as uncached allocations, and skipping them saves two syscalls per
+    /// mapping. This must only be used for coherent buffers: the CPU could otherwise access stale
+    /// data, or the devices miss the CPU writes. Defaults to false.
+    #[must_use]
+    pub fn skip_sync(mut self, skip_sync: bool) -> Self {
+        self.skip_sync = skip_sync;
+        self
+    }
+
+    /// Asks the kernel to place the mapping at `address`
+    ///
+    /// The address is only a hint, and the kernel is free to place the mapping elsewhere. It must
+    /// be a multiple of [`DmaBuf::offset_alignment`].
+    #[must_use]
+    pub fn at_address(mut self, address: *mut c_void) -> Self {
+        self.address = NonZeroUsize::new(address.addr());
+        self.placement = Placement::Hint;
+        self
+    }
+
+    /// Places the mapping at `address`, failing if it overlaps an existing mapping
+    ///
+    /// The mapping is created with `MAP_FIXED_NOREPLACE`, and the mmap call fails with `EEXIST` if
+    /// the range is already mapped. Kernels older than 4.17 ignore the flag and treat the address
+    /// as a hint. The address must be a multiple of [`DmaBuf::offset_alignment`].
+    #[must_use]
+    pub fn at_address_noreplace(mut self, address: *mut c_void) -> Self {
+        self.address = NonZeroUsize::new(address.addr());
+        self.placement = Placement::NoReplace;
+        self
+    }
+
+    /// Places the mapping at `address`, replacing any existing mapping
+    ///
+    /// The mapping is created with `MAP_FIXED`. The address must be a multiple of
+    /// [`DmaBuf::offset_alignment`]. When the mapping is released or dropped, the range is
+    /// unmapped, and doesn't revert to what it was mapped to before.
+    ///
+    /// # Safety
+    ///
+    /// Any existing mapping in the range is silently replaced. The caller must make sure that
+    /// nothing in the range is used anymore, including memory reserved by the allocator, the
+    /// stacks or the program itself, or that it's a range the caller reserved for that purpose.
+    #[must_use]
+    pub unsafe fn at_fixed_address(mut self, address: *mut c_void) -> Self {
+        self.address = NonZeroUsize::new(address.addr());
+        self.placement = Placement::Fixed;
+        self
+    }
+
+    /// Sets the number of times the mapping is attempted again if mmap fails with `EAGAIN`
+    ///
+    /// The kernel can fail to map a buffer with `EAGAIN` under memory pressure, which usually
+    /// succeeds if tried again right away. With the standard library, a short delay is waited for
+    /// between two attempts. Defaults to 3.
+    #[must_use]
+    pub fn mmap_retries(mut self, retries: u32) -> Self {
+        self.mmap_retries = Some(retries);
+        self
+    }
+
+    fn address(self) -> usize {
+        self.address.map_or(0, NonZeroUsize::get)
+    }
+
+    fn layout(self, buf: &DmaBuf) -> Result<Layout, MapError> {
+        let size = match self.buffer_len {
+            Some(len) => len,
+            None => buf.len()?,
+        };
+
+        mapping_layout(size, self.offset, self.address(), self.len, page_size())
+    }
+
+    fn begin_access(
+        self,
+        begin_access: fn(BorrowedFd<'_>) -> Result<(), MapError>,
+    ) -> fn(BorrowedFd<'_>) -> Result<(), MapError> {
+        if self.skip_sync {
+            |_fd| Ok(())
+        } else {
+            begin_access
+        }
+    }
+
+    fn mmap(
+        self,
+        buf: &DmaBuf,
+        prot: ProtFlags,
+        sharing: MapFlags,
+        layout: Layout,
+        begin_access: fn(BorrowedFd<'_>) -> Result<(), MapError>,
+    ) -> Result<*mut u8, MapError> {
+        let mmap = buf.mmap(
+            prot,
+            self.flags(sharing),
+            ptr::without_provenance_mut(self.address()),
+            layout.offset,
+            layout.mapped_len,
+            self.mmap_retries.unwrap_or(MMAP_RETRIES),
+        )?;
+
+        if let Err(e) = self.begin_access(begin_access)(buf.as_fd()) {
+            // SAFETY: We've just mapped that region, and it isn't referenced anywhere else.
+            if unsafe { munmap(mmap.cast::<c_void>(), layout.mapped_len) }.is_err() {
+                warn!("unmap failed!");
+            }
+
+            return Err(e);
+        }
+
+        Ok(mmap)
+    }
+
+    fn flags(self, sharing: MapFlags) -> MapFlags {
+        let mut flags = sharing;
+
+        if self.populate {
+            flags |= MapFlags::POPULATE;
+        }
+
+        if self.address.is_some() {
+            match self.placement {
+                Placement::Hint => {}
+                Placement::NoReplace => flags |= MapFlags::FIXED_NOREPLACE,
+                Placement::Fixed => flags |= MapFlags::FIXED,
+            }
+        }
+
+        flags
+    }
+
+    /// Maps `buf` for the CPU to read it
+    ///
+    /// See [`DmaBuf::memory_map_ro`].
+    ///
+    /// # Errors
+    ///
+    /// Will return [`MapError::EmptyBuffer`] if the buffer or the range is empty,
+    /// [`MapError::UnalignedAddress`] if the address isn't properly aligned,
+    /// [`MapError::RangeOutOfBounds`] if the range doesn't fit in the buffer, or an error if either
+    /// the Buffer's length can't be retrieved, the mmap call fails or the CPU access can't be
+    /// started.
+    pub fn map(self, buf: &DmaBuf) -> Result<MappedDmaBufRo<'_>, MapError> {
+        let layout = self.layout(buf)?;
+        let mmap = self.mmap(
+            buf,
+            ProtFlags::READ,
+            MapFlags::SHARED,
+            layout,
+            dma_buf_begin_cpu_read_access,
+        )?;
+
+        Ok(Mapping::new(buf, mmap, layout, !self.skip_sync))
+    }
+
+    /// Maps `buf` for the CPU to read from and write to it
+    ///
+    /// See [`DmaBuf::memory_map_rw`].
+    ///
+    /// # Errors
+    ///
+    /// Will return [`MapError::EmptyBuffer`] if the buffer or the range is empty,
+    /// [`MapError::UnalignedAddress`] if the address isn't properly aligned,
+    /// [`MapError::RangeOutOfBounds`] if the range doesn't fit in the buffer, or an error if either
+    /// the Buffer's length can't be retrieved, the mmap call fails or the CPU access can't be
+    /// started.
+    pub fn map_mut(self, buf: &mut DmaBuf) -> Result<MappedDmaBufRw<'_>, MapError> {
+        let layout = self.layout(buf)?;
+        let mmap = self.mmap(
+            buf,
+            ProtFlags::READ | ProtFlags::WRITE,
+            MapFlags::SHARED,
+            layout,
+            dma_buf_begin_cpu_readwrite_access,
+        )?;
+
+        Ok(Mapping::new(buf, mmap, layout, !self.skip_sync))
+    }
+
+    /// Maps `buf` for the CPU to write to it
+    ///
+    /// See [`DmaBuf::memory_map_wo`].
+    ///
+    /// # Errors
+    ///
+    /// Will return [`MapError::EmptyBuffer`] if the buffer or the range is empty,
+    /// [`MapError::UnalignedAddress`] if the address isn't properly aligned,
+    /// [`MapError::RangeOutOfBounds`] if the range doesn't fit in the buffer, or an error if either
+    /// the Buffer's length can't be retrieved, the mmap call fails or the CPU access can't be
+    /// started.
+    pub fn map_wo(self, buf: &mut DmaBuf) -> Result<MappedDmaBufWo<'_>, MapError> {
+        self.map_write(buf)
+    }
+
+    // DmaBuf::split_access maps the buffer for reading along with this mapping, and can't go
+    // through map_wo() and its exclusive borrow. Its callers guarantee that the buffer isn't
+    // written to while the read-only mapping content is borrowed.
+    pub(crate) fn map_write(self, buf: &DmaBuf) -> Result<MappedDmaBufWo<'_>, MapError> {
+        let layout = self.layout(buf)?;
+        let mmap = self.mmap(
+            buf,
+            ProtFlags::WRITE,
+            MapFlags::SHARED,
+            layout,
+            dma_buf_begin_cpu_write_access,
+        )?;
+
+        Ok(Mapping::new(buf, mmap, layout, !self.skip_sync))
+    }
+
+    /// Maps a private, copy-on-write, copy of `buf` for the CPU to read from and write to it
+    ///
+    /// See [`DmaBuf::memory_map_copy`].
+    ///
+    /// # Errors
+    ///
+    /// Will return [`MapError::EmptyBuffer`] if the buffer or the range is empty,
+    /// [`MapError::UnalignedAddress`] if the address isn't properly aligned,
+    /// [`MapError::RangeOutOfBounds`] if the range doesn't fit in the buffer, or an error if either
+    /// the Buffer's length can't be retrieved, the mmap call fails or the CPU access can't be
+    /// started.
+    pub fn map_copy(self, buf: &DmaBuf) -> Result<MappedDmaBufRw<'_>, MapError> {
+        let layout = self.layout(buf)?;
+        let mmap = self.mmap(
+            buf,
+            ProtFlags::READ | ProtFlags::WRITE,
+            MapFlags::PRIVATE,
+            layout,
+            dma_buf_begin_cpu_read_access,
+        )?;
+
+        let mut mapping = Mapping::new(buf, mmap, layout, !self.skip_sync);
+        mapping.access = SyncMode::Read;
+
+        Ok(mapping)
+    }
+}
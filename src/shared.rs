@@ -0,0 +1,43 @@
+// Copyright 2020-2021, Cerno
+// Licensed under the MIT License
+// See the LICENSE file or <http://opensource.org/licenses/MIT>
+
+use alloc::sync::Arc;
+use core::ops::Deref;
+
+use crate::MappedDmaBufRo;
+
+/// A read-only mapping of a [`DmaBuf`](crate::DmaBuf) shared between several readers
+///
+/// Each call to [`DmaBuf::memory_map_ro`](crate::DmaBuf::memory_map_ro) maps the buffer and starts
+/// a CPU access again. A `SharedMapping` instead is reference-counted: cloning it is cheap and
+/// shares the same mapping, and the CPU access ends and the buffer is unmapped when the last
+/// clone is dropped.
+#[derive(Clone, Debug)]
+pub struct SharedMapping<'a>(Arc<MappedDmaBufRo<'a>>);
+
+impl<'a> SharedMapping<'a> {
+    pub(crate) fn new(mapping: MappedDmaBufRo<'a>) -> Self {
+        Self(Arc::new(mapping))
+    }
+
+    /// Returns the content of the mapping
+    #[must_use]
+    pub fn as_slice(&self) -> &[u8] {
+        self.0.as_slice()
+    }
+
+    /// Returns the number of clones sharing the mapping
+    #[must_use]
+    pub fn ref_count(&self) -> usize {
+        Arc::strong_count(&self.0)
+    }
+}
+
+impl Deref for SharedMapping<'_> {
+    type Target = [u8];
+
+    fn deref(&self) -> &Self::Target {
+        self.as_slice()
+    }
+}
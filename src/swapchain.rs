@@ -0,0 +1,69 @@
+// Copyright 2020-2021, Cerno
+// Licensed under the MIT License
+// See the LICENSE file or <http://opensource.org/licenses/MIT>
+
+use alloc::vec::Vec;
+
+use crate::DmaBuf;
+
+/// A set of [`DmaBuf`] used in turn
+///
+/// Compositors and video pipelines usually render into a buffer while the previous ones are still
+/// being displayed or consumed. A `SwapChain` holds those buffers and keeps track of the one to
+/// use next: [`SwapChain::acquire`] returns the current buffer to fill, and
+/// [`SwapChain::present`] hands it off and rotates to the next one.
+#[derive(Debug)]
+pub struct SwapChain {
+    buffers: Vec<DmaBuf>,
+    current: usize,
+}
+
+impl SwapChain {
+    /// Creates a new `SwapChain` rotating over `buffers`, in order
+    ///
+    /// Returns `None` if `buffers` is empty.
+    #[must_use]
+    pub fn new(buffers: Vec<DmaBuf>) -> Option<Self> {
+        if buffers.is_empty() {
+            return None;
+        }
+
+        Some(Self {
+            buffers,
+            current: 0,
+        })
+    }
+
+    /// Returns the buffer to fill next
+    pub fn acquire(&mut self) -> &mut DmaBuf {
+        &mut self.buffers[self.current]
+    }
+
+    /// Presents the current buffer and rotates to the next one
+    ///
+    /// The buffer that has just been presented is returned so that it can be handed to its
+    /// consumer.
+    pub fn present(&mut self) -> &DmaBuf {
+        let presented = self.current;
+
+        self.current = if presented + 1 == self.buffers.len() {
+            0
+        } else {
+            presented + 1
+        };
+
+        &self.buffers[presented]
+    }
+
+    /// Returns all the buffers of the `SwapChain`, in the order they were given
+    #[must_use]
+    pub fn buffers(&self) -> &[DmaBuf] {
+        &self.buffers
+    }
+
+    /// Consumes the `SwapChain`, returning its buffers
+    #[must_use]
+    pub fn into_inner(self) -> Vec<DmaBuf> {
+        self.buffers
+    }
+}
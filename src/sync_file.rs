@@ -0,0 +1,79 @@
+// Copyright 2020-2021, Cerno
+// Licensed under the MIT License
+// See the LICENSE file or <http://opensource.org/licenses/MIT>
+
+use core::time::Duration;
+
+use log::debug;
+use rustix::{
+    event::PollFlags,
+    fd::{AsFd, AsRawFd, BorrowedFd, OwnedFd, RawFd},
+};
+
+use crate::{ioctl::sync_file_merge, poll_fd, MapError};
+
+/// An explicit synchronization fence
+///
+/// A `sync_file` is a file descriptor wrapping one or more fences, used to explicitly synchronize
+/// the accesses of the CPU and the devices to a buffer. It becomes readable once all its fences
+/// have signalled.
+#[derive(Debug)]
+pub struct SyncFile(OwnedFd);
+
+impl SyncFile {
+    /// Waits for all the fences to signal, for at most `timeout`
+    ///
+    /// If `timeout` is `None`, this will wait forever. Returns whether the fences signalled before
+    /// the timeout expired.
+    ///
+    /// # Errors
+    ///
+    /// Will return [`MapError::FdAccess`] if polling the file descriptor fails.
+    pub fn wait(&self, timeout: Option<Duration>) -> Result<bool, MapError> {
+        debug!("Waiting for sync_file {:#?} for {timeout:?}", self.0);
+
+        poll_fd(self.as_fd(), PollFlags::IN, timeout)
+    }
+
+    /// Returns whether all the fences have signalled, without blocking
+    ///
+    /// # Errors
+    ///
+    /// Will return [`MapError::FdAccess`] if polling the file descriptor fails.
+    pub fn is_signaled(&self) -> Result<bool, MapError> {
+        self.wait(Some(Duration::ZERO))
+    }
+
+    /// Merges two `SyncFile` into a new one, signalled once the fences of both are
+    ///
+    /// # Errors
+    ///
+    /// Will return [`MapError::FdAccess`] if the `SYNC_IOC_MERGE` ioctl fails.
+    pub fn merge(&self, other: &Self) -> Result<Self, MapError> {
+        sync_file_merge(self.as_fd(), other.as_fd()).map(Self)
+    }
+}
+
+impl From<OwnedFd> for SyncFile {
+    fn from(owned: OwnedFd) -> Self {
+        Self(owned)
+    }
+}
+
+impl From<SyncFile> for OwnedFd {
+    fn from(sync_file: SyncFile) -> Self {
+        sync_file.0
+    }
+}
+
+impl AsFd for SyncFile {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.0.as_fd()
+    }
+}
+
+impl AsRawFd for SyncFile {
+    fn as_raw_fd(&self) -> RawFd {
+        self.0.as_raw_fd()
+    }
+}
@@ -0,0 +1,841 @@
+// Copyright 2020-2021, Cerno
+// Licensed under the MIT License
+// See the LICENSE file or <http://opensource.org/licenses/MIT>
+
+#![cfg(feature = "std")]
+
+use core::time::Duration;
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, Read as _, Seek as _, SeekFrom, Write as _},
+    os::unix::fs::FileExt as _,
+};
+
+use rustix::{
+    fd::{AsFd as _, AsRawFd, BorrowedFd, FromRawFd as _, IntoRawFd as _, OwnedFd},
+    fs::{memfd_create, MemfdFlags},
+    io::Errno,
+    param::page_size,
+};
+
+use crate::{
+    checked_subrange, ioctl, mapping_layout, page_offsets, poll_timeout_ms, seek_position, DmaBuf,
+    DmaHeap, MapError, MapOptions, SyncMode,
+};
+
+/// Creates a memfd holding `content`, to stand in for a dma-buf
+///
+/// The memfd doesn't implement the dma-buf ioctls, so it must be mapped with
+/// [`MapOptions::skip_sync`].
+fn memfd(content: &[u8]) -> File {
+    let fd = memfd_create("dma-buf-test", MemfdFlags::CLOEXEC).expect("Couldn't create a memfd");
+    let mut file = File::from(fd);
+
+    file.write_all(content).expect("Couldn't fill the memfd");
+
+    file
+}
+
+/// Opens a new file descriptor for `file`, with the access mode set in `options`
+fn reopen(file: &File, options: &OpenOptions) -> DmaBuf {
+    let path = format!("/proc/self/fd/{}", file.as_raw_fd());
+
+    DmaBuf::from(options.open(path).expect("Couldn't reopen the memfd"))
+}
+
+/// Returns the permissions of the memory mapping `address` is in, as found in `/proc/self/maps`
+fn mapping_perms(address: *const u8) -> String {
+    let address = address as usize;
+    let maps = std::fs::read_to_string("/proc/self/maps").expect("Couldn't read the mappings");
+
+    maps.lines()
+        .find_map(|line| {
+            let (range, rest) = line.split_once(' ')?;
+            let (start, end) = range.split_once('-')?;
+            let start = usize::from_str_radix(start, 16).ok()?;
+            let end = usize::from_str_radix(end, 16).ok()?;
+
+            (start..end)
+                .contains(&address)
+                .then(|| rest.split(' ').next().map(str::to_owned))
+                .flatten()
+        })
+        .expect("Address isn't mapped")
+}
+
+const fn assert_send_sync<T: Send + Sync + 'static>() {}
+
+#[test]
+fn map_error_is_send_sync() {
+    assert_send_sync::<MapError>();
+}
+
+#[test]
+fn map_options_is_send_sync() {
+    assert_send_sync::<MapOptions>();
+}
+
+// The sync helpers used to return a different error type than the mapping functions.
+const SYNC_HELPERS: [fn(BorrowedFd<'_>) -> Result<(), MapError>; 6] = [
+    ioctl::dma_buf_begin_cpu_read_access,
+    ioctl::dma_buf_begin_cpu_readwrite_access,
+    ioctl::dma_buf_begin_cpu_write_access,
+    ioctl::dma_buf_end_cpu_read_access,
+    ioctl::dma_buf_end_cpu_readwrite_access,
+    ioctl::dma_buf_end_cpu_write_access,
+];
+
+#[test]
+fn sync_helpers_return_map_error() {
+    let file = memfd(b"sync");
+
+    for helper in SYNC_HELPERS {
+        let Err(MapError::Unsupported { operation }) = helper(file.as_fd()) else {
+            panic!("Syncing a memfd didn't fail as expected");
+        };
+
+        assert_eq!(
+            operation, "DMA_BUF_IOCTL_SYNC",
+            "Unexpected operation in the error"
+        );
+    }
+}
+
+#[test]
+fn map_ro_read_only_fd() {
+    let file = memfd(b"read-only");
+    let buf = reopen(&file, OpenOptions::new().read(true));
+
+    let mapping = MapOptions::new()
+        .skip_sync(true)
+        .map(&buf)
+        .expect("Couldn't map a read-only fd for reading");
+
+    assert_eq!(
+        mapping.as_slice(),
+        b"read-only",
+        "Mapping content doesn't match"
+    );
+    assert_eq!(
+        mapping_perms(mapping.as_ptr()),
+        "r--s",
+        "Mapping isn't read-only"
+    );
+}
+
+#[test]
+fn map_rw_read_only_fd() {
+    let file = memfd(b"read-only");
+    let mut buf = reopen(&file, OpenOptions::new().read(true));
+
+    let Err(MapError::MappingFailed { errno, .. }) =
+        MapOptions::new().skip_sync(true).map_mut(&mut buf)
+    else {
+        panic!("Mapping a read-only fd for writing didn't fail as expected");
+    };
+
+    assert_eq!(errno, Some(Errno::ACCESS), "Unexpected mmap error");
+}
+
+#[test]
+fn map_wo_write_only_mapping() {
+    let mut buf = DmaBuf::from(memfd(b"write-only"));
+
+    let mut mapping = MapOptions::new()
+        .skip_sync(true)
+        .map_wo(&mut buf)
+        .expect("Couldn't map the buffer for writing");
+
+    assert_eq!(
+        mapping_perms(mapping.as_mut_ptr()),
+        "-w-s",
+        "Mapping isn't write-only"
+    );
+}
+
+#[test]
+fn map_rw_read_write_mapping() {
+    let mut buf = DmaBuf::from(memfd(b"read-write"));
+
+    let mapping = MapOptions::new()
+        .skip_sync(true)
+        .map_mut(&mut buf)
+        .expect("Couldn't map the buffer for reading and writing");
+
+    assert_eq!(
+        mapping_perms(mapping.as_slice().as_ptr()),
+        "rw-s",
+        "Mapping isn't readable and writable"
+    );
+}
+
+#[test]
+fn map_wo_write_only_fd() {
+    let file = memfd(b"write-only");
+    let mut buf = reopen(&file, OpenOptions::new().write(true));
+
+    let Err(MapError::MappingFailed { errno, .. }) =
+        MapOptions::new().skip_sync(true).map_wo(&mut buf)
+    else {
+        panic!("Mapping a write-only fd didn't fail as expected");
+    };
+
+    assert_eq!(errno, Some(Errno::ACCESS), "Unexpected mmap error");
+}
+
+#[test]
+fn map_len_not_page_multiple() {
+    let content = [0xa5; 100];
+    let buf = DmaBuf::from(memfd(&content));
+
+    let mapping = MapOptions::new()
+        .skip_sync(true)
+        .map(&buf)
+        .expect("Couldn't map the buffer");
+
+    assert_eq!(
+        mapping.len(),
+        content.len(),
+        "Mapping length isn't the buffer size"
+    );
+    assert_eq!(mapping.as_slice(), content, "Mapping content doesn't match");
+    assert_eq!(
+        mapping.mapped_len(),
+        page_size(),
+        "Mapped length isn't a whole page"
+    );
+}
+
+#[test]
+fn mapping_deref() {
+    fn first(bytes: &[u8]) -> Option<u8> {
+        bytes.first().copied()
+    }
+
+    let buf = DmaBuf::from(memfd(b"deref"));
+
+    let mapping = MapOptions::new()
+        .skip_sync(true)
+        .map(&buf)
+        .expect("Couldn't map the buffer");
+
+    assert_eq!(
+        first(&mapping),
+        Some(b'd'),
+        "Deref doesn't give the content"
+    );
+    assert_eq!(mapping[1], b'e', "Indexing doesn't give the content");
+    assert_eq!(
+        &mapping[2..],
+        b"ref",
+        "Range indexing doesn't give the content"
+    );
+    assert!(
+        mapping.starts_with(b"de"),
+        "Slice methods don't go through Deref"
+    );
+}
+
+#[test]
+fn mapping_deref_mut() {
+    let mut buf = DmaBuf::from(memfd(b"deref"));
+
+    let mut mapping = MapOptions::new()
+        .skip_sync(true)
+        .map_mut(&mut buf)
+        .expect("Couldn't map the buffer");
+
+    mapping[0] = b'D';
+    mapping[1..3].copy_from_slice(b"ER");
+    mapping.swap(3, 4);
+
+    assert_eq!(
+        mapping.as_slice(),
+        b"DERfe",
+        "DerefMut doesn't modify the content"
+    );
+}
+
+#[test]
+fn mapping_into_ro() {
+    let file = memfd(b"downgrade");
+    let mut buf = reopen(&file, OpenOptions::new().read(true).write(true));
+
+    let mut mapping = MapOptions::new()
+        .skip_sync(true)
+        .map_mut(&mut buf)
+        .expect("Couldn't map the buffer");
+    assert_eq!(mapping.access, SyncMode::ReadWrite, "Unexpected access");
+
+    mapping[..4].copy_from_slice(b"DOWN");
+    let ptr = mapping.as_slice().as_ptr();
+
+    // Only the write access is ended, the read access is kept along with the mapping itself.
+    let mapping = mapping.into_ro();
+    assert_eq!(mapping.access, SyncMode::Read, "Read access wasn't kept");
+    assert_eq!(mapping.as_slice().as_ptr(), ptr, "Buffer was mapped again");
+    assert_eq!(
+        mapping.as_slice(),
+        b"DOWNgrade",
+        "Written content isn't readable"
+    );
+
+    mapping.release().expect("Couldn't release the mapping");
+    assert_eq!(
+        content(&file, 9),
+        b"DOWNgrade",
+        "Buffer content doesn't match"
+    );
+}
+
+#[test]
+fn mapping_read() {
+    let content = b"Hello, reader";
+    let buf = DmaBuf::from(memfd(content));
+
+    let mut mapping = MapOptions::new()
+        .skip_sync(true)
+        .map(&buf)
+        .expect("Couldn't map the buffer");
+
+    let mut copied = Vec::new();
+    let count = io::copy(&mut mapping, &mut copied).expect("Couldn't copy from the mapping");
+
+    assert_eq!(count, 13, "Copy didn't read the whole buffer");
+    assert_eq!(copied, content, "Copied content doesn't match");
+
+    mapping
+        .seek(SeekFrom::Start(7))
+        .expect("Couldn't seek in the mapping");
+
+    let mut rest = String::new();
+    mapping
+        .read_to_string(&mut rest)
+        .expect("Couldn't read from the mapping");
+
+    assert_eq!(rest, "reader", "Reading after the seek doesn't match");
+}
+
+/// Returns the content of `file`, read without going through a mapping
+fn content(file: &File, len: usize) -> Vec<u8> {
+    let mut content = vec![0; len];
+    file.read_exact_at(&mut content, 0)
+        .expect("Couldn't read the memfd");
+
+    content
+}
+
+#[test]
+fn mapping_write_fill() {
+    let file = memfd(&[0; 8]);
+    let mut buf = reopen(&file, OpenOptions::new().read(true).write(true));
+
+    let mut mapping = MapOptions::new()
+        .skip_sync(true)
+        .map_mut(&mut buf)
+        .expect("Couldn't map the buffer");
+
+    mapping
+        .write_all(b"12345678")
+        .expect("Couldn't fill the mapping");
+    mapping.flush().expect("Couldn't flush the mapping");
+    drop(mapping);
+
+    assert_eq!(
+        content(&file, 8),
+        b"12345678",
+        "Buffer content doesn't match"
+    );
+}
+
+#[test]
+fn mapping_write_overrun() {
+    let file = memfd(&[0; 8]);
+    let mut buf = reopen(&file, OpenOptions::new().read(true).write(true));
+
+    let mut mapping = MapOptions::new()
+        .skip_sync(true)
+        .map_wo(&mut buf)
+        .expect("Couldn't map the buffer");
+
+    let count = mapping
+        .write(b"0123456789")
+        .expect("Couldn't write to the mapping");
+    assert_eq!(count, 8, "Write wasn't cut short at the end of the buffer");
+
+    let count = mapping.write(b"89").expect("Couldn't write to the mapping");
+    assert_eq!(count, 0, "Write past the end of the buffer wasn't empty");
+
+    mapping
+        .seek(SeekFrom::Start(6))
+        .expect("Couldn't seek in the mapping");
+    let err = mapping
+        .write_all(b"abcd")
+        .expect_err("Writing past the end of the buffer didn't fail");
+    assert_eq!(
+        err.kind(),
+        io::ErrorKind::WriteZero,
+        "Unexpected write error"
+    );
+    drop(mapping);
+
+    assert_eq!(
+        content(&file, 8),
+        b"012345ab",
+        "Buffer content doesn't match"
+    );
+}
+
+#[test]
+fn dma_buf_into_raw_fd() {
+    let file = memfd(b"raw");
+    let raw = file.as_raw_fd();
+
+    let buf = DmaBuf::from(file);
+    assert_eq!(
+        buf.as_raw_fd(),
+        raw,
+        "DmaBuf doesn't hold the file descriptor"
+    );
+
+    let released = buf.into_raw_fd();
+    assert_eq!(released, raw, "DmaBuf didn't give the file descriptor back");
+
+    // SAFETY: The DmaBuf gave the ownership of the file descriptor back, and it's still open.
+    let buf = unsafe { DmaBuf::from_raw_fd(released) };
+
+    let owned = OwnedFd::from(buf);
+    assert_eq!(
+        owned.as_raw_fd(),
+        raw,
+        "DmaBuf didn't give the file descriptor back"
+    );
+}
+
+#[test]
+fn retry_on_eintr_once() {
+    let mut calls = 0;
+
+    let ret = ioctl::retry_on_eintr(|| {
+        calls += 1;
+
+        if calls == 1 {
+            Err(Errno::INTR)
+        } else {
+            Ok(calls)
+        }
+    });
+
+    assert_eq!(ret, Ok(2), "The call wasn't retried after EINTR");
+}
+
+#[test]
+fn retry_on_eintr_other_error() {
+    let mut calls = 0;
+
+    let ret: Result<(), Errno> = ioctl::retry_on_eintr(|| {
+        calls += 1;
+
+        Err(Errno::AGAIN)
+    });
+
+    assert_eq!(ret, Err(Errno::AGAIN), "The error wasn't returned");
+    assert_eq!(
+        calls, 1,
+        "The call was retried after another error than EINTR"
+    );
+}
+
+const PAGE: usize = 4096;
+
+#[test]
+fn mapping_layout_huge_size() {
+    let Err(MapError::TooLarge { .. }) = mapping_layout(usize::MAX, 0, 0, None, PAGE) else {
+        panic!("Mapping a buffer too large to round up didn't fail as expected");
+    };
+
+    let Err(MapError::TooLarge { .. }) = mapping_layout(usize::MAX, 1, 0, None, PAGE) else {
+        panic!("Mapping a range too large to round up didn't fail as expected");
+    };
+}
+
+#[test]
+fn mapping_layout_whole_buffer() {
+    let layout = mapping_layout(3 * PAGE + 1, 0, 0, None, PAGE).expect("Invalid layout");
+
+    assert_eq!(
+        layout.offset, 0,
+        "Mapping doesn't start at the start of the buffer"
+    );
+    assert_eq!(
+        layout.delta, 0,
+        "Mapping doesn't start at the start of its first page"
+    );
+    assert_eq!(
+        layout.len,
+        3 * PAGE + 1,
+        "Mapping doesn't cover the whole buffer"
+    );
+    assert_eq!(
+        layout.mapped_len,
+        4 * PAGE,
+        "Mapping doesn't cover whole pages"
+    );
+}
+
+#[test]
+fn mapping_layout_unaligned_offset() {
+    let layout = mapping_layout(4 * PAGE, PAGE + 4, 0, Some(10), PAGE).expect("Invalid layout");
+
+    assert_eq!(
+        layout.offset, PAGE,
+        "Mapping doesn't start on a page boundary"
+    );
+    assert_eq!(
+        layout.delta, 4,
+        "Range doesn't start at the requested offset"
+    );
+    assert_eq!(layout.len, 10, "Range isn't as long as requested");
+    assert_eq!(
+        layout.mapped_len, PAGE,
+        "Mapping doesn't cover a single page"
+    );
+
+    let layout = mapping_layout(4 * PAGE, PAGE - 4, 0, Some(10), PAGE).expect("Invalid layout");
+
+    assert_eq!(layout.offset, 0, "Mapping doesn't start on a page boundary");
+    assert_eq!(
+        layout.delta,
+        PAGE - 4,
+        "Range doesn't start at the requested offset"
+    );
+    assert_eq!(
+        layout.mapped_len,
+        2 * PAGE,
+        "Mapping doesn't cover both pages"
+    );
+
+    let layout = mapping_layout(4 * PAGE, PAGE + 4, 0, None, PAGE).expect("Invalid layout");
+
+    assert_eq!(
+        layout.len,
+        3 * PAGE - 4,
+        "Range doesn't cover the rest of the buffer"
+    );
+    assert_eq!(
+        layout.mapped_len,
+        3 * PAGE,
+        "Mapping doesn't cover the last pages"
+    );
+}
+
+#[test]
+fn mapping_layout_bounds() {
+    let layout =
+        mapping_layout(2 * PAGE + 8, PAGE + 8, 0, Some(PAGE), PAGE).expect("Invalid layout");
+    assert_eq!(
+        layout.len, PAGE,
+        "Range ending on the buffer end was rejected"
+    );
+
+    let Err(MapError::RangeOutOfBounds { offset, len, bound }) =
+        mapping_layout(2 * PAGE + 8, PAGE + 8, 0, Some(PAGE + 1), PAGE)
+    else {
+        panic!("Range past the end of the buffer didn't fail as expected");
+    };
+    assert_eq!(
+        (offset, len, bound),
+        (PAGE + 8, PAGE + 1, 2 * PAGE + 8),
+        "Unexpected range in the error"
+    );
+
+    let Err(MapError::RangeOutOfBounds { .. }) = mapping_layout(PAGE, usize::MAX, 0, Some(2), PAGE)
+    else {
+        panic!("Range overflowing didn't fail as expected");
+    };
+}
+
+#[test]
+fn mapping_layout_empty() {
+    let Err(MapError::EmptyBuffer) = mapping_layout(0, 0, 0, None, PAGE) else {
+        panic!("Empty buffer didn't fail as expected");
+    };
+}
+
+#[test]
+fn mapping_layout_empty_range() {
+    let Err(MapError::EmptyBuffer) = mapping_layout(PAGE, 8, 0, Some(0), PAGE) else {
+        panic!("Empty range didn't fail as expected");
+    };
+
+    let Err(MapError::EmptyBuffer) = mapping_layout(PAGE, PAGE, 0, None, PAGE) else {
+        panic!("Range starting at the end of the buffer didn't fail as expected");
+    };
+}
+
+#[test]
+fn mapping_layout_unaligned_address() {
+    let Err(MapError::UnalignedAddress { address, alignment }) =
+        mapping_layout(PAGE, 0, PAGE + 1, None, PAGE)
+    else {
+        panic!("Unaligned address didn't fail as expected");
+    };
+    assert_eq!(
+        (address, alignment),
+        (PAGE + 1, PAGE),
+        "Unexpected address in the error"
+    );
+
+    mapping_layout(PAGE, 0, 2 * PAGE, None, PAGE).expect("Aligned address was rejected");
+}
+
+#[test]
+fn checked_subrange_bounds() {
+    assert_eq!(
+        checked_subrange(2, 6, 8).ok(),
+        Some(2..8),
+        "Range ending on the bound was rejected"
+    );
+    assert_eq!(
+        checked_subrange(8, 0, 8).ok(),
+        Some(8..8),
+        "Empty range at the bound was rejected"
+    );
+
+    let Err(MapError::RangeOutOfBounds { offset, len, bound }) = checked_subrange(2, 7, 8) else {
+        panic!("Range past the bound didn't fail as expected");
+    };
+    assert_eq!(
+        (offset, len, bound),
+        (2, 7, 8),
+        "Unexpected range in the error"
+    );
+
+    let Err(MapError::RangeOutOfBounds { .. }) = checked_subrange(usize::MAX, 1, usize::MAX) else {
+        panic!("Range overflowing didn't fail as expected");
+    };
+}
+
+#[test]
+fn page_offsets_aligned() {
+    let offsets: Vec<_> = page_offsets(0, 3 * PAGE, PAGE).collect();
+
+    assert_eq!(offsets, [0, PAGE, 2 * PAGE], "Not one offset per page");
+}
+
+#[test]
+fn page_offsets_unaligned() {
+    let offsets: Vec<_> = page_offsets(10, PAGE, PAGE).collect();
+    assert_eq!(offsets, [0, PAGE - 10], "Not one offset per page");
+
+    let offsets: Vec<_> = page_offsets(10, PAGE - 10, PAGE).collect();
+    assert_eq!(
+        offsets,
+        [0],
+        "Range within a single page has several offsets"
+    );
+
+    assert_eq!(
+        page_offsets(10, 0, PAGE).count(),
+        0,
+        "Empty range has offsets"
+    );
+}
+
+#[test]
+fn seek_position_bounds() {
+    assert_eq!(
+        seek_position(4, 8, SeekFrom::Start(16)).ok(),
+        Some(16),
+        "Seeking past the end isn't allowed"
+    );
+    assert_eq!(
+        seek_position(4, 8, SeekFrom::End(-8)).ok(),
+        Some(0),
+        "Seeking from the end doesn't match"
+    );
+    assert_eq!(
+        seek_position(4, 8, SeekFrom::Current(-4)).ok(),
+        Some(0),
+        "Seeking from the current position doesn't match"
+    );
+
+    let err = seek_position(4, 8, SeekFrom::Current(-5)).expect_err("Negative seek didn't fail");
+    assert_eq!(
+        err.kind(),
+        io::ErrorKind::InvalidInput,
+        "Unexpected seek error"
+    );
+}
+
+#[test]
+fn map_empty_buffer() {
+    let mut buf = DmaBuf::from(memfd(&[]));
+
+    let Err(MapError::EmptyBuffer) = MapOptions::new().skip_sync(true).map(&buf) else {
+        panic!("Mapping an empty buffer didn't fail as expected");
+    };
+
+    let Err(MapError::EmptyBuffer) = MapOptions::new().skip_sync(true).map_mut(&mut buf) else {
+        panic!("Mapping an empty buffer didn't fail as expected");
+    };
+}
+
+#[test]
+fn map_error_into_io_error_errno() {
+    let err = io::Error::from(MapError::fd_access(Errno::PERM));
+    assert_eq!(
+        err.raw_os_error(),
+        Some(Errno::PERM.raw_os_error()),
+        "Errno was lost"
+    );
+
+    let err = io::Error::from(MapError::mapping_failed(Errno::ACCESS));
+    assert_eq!(
+        err.raw_os_error(),
+        Some(Errno::ACCESS.raw_os_error()),
+        "Errno was lost"
+    );
+
+    let err = io::Error::from(MapError::Batch {
+        index: 1,
+        source: Box::new(MapError::out_of_memory(Errno::NOMEM)),
+    });
+    assert_eq!(
+        err.raw_os_error(),
+        Some(Errno::NOMEM.raw_os_error()),
+        "Errno was lost"
+    );
+}
+
+#[test]
+fn map_error_into_io_error_kind() {
+    let conversion = u8::try_from(256u32).expect_err("256 fits in a u8");
+    let err = io::Error::from(MapError::from(conversion));
+    assert_eq!(
+        err.kind(),
+        io::ErrorKind::InvalidInput,
+        "Unexpected error kind"
+    );
+
+    let err = io::Error::from(MapError::Timeout(Duration::from_millis(1)));
+    assert_eq!(err.kind(), io::ErrorKind::TimedOut, "Unexpected error kind");
+
+    let inner = err.into_inner().expect("The MapError wasn't kept");
+    let Ok(inner) = inner.downcast::<MapError>() else {
+        panic!("The inner error isn't a MapError");
+    };
+    let MapError::Timeout(timeout) = *inner else {
+        panic!("Unexpected inner error {inner:?}");
+    };
+    assert_eq!(timeout, Duration::from_millis(1), "Unexpected timeout");
+}
+
+#[test]
+fn poll_timeout_rounds_up() {
+    assert_eq!(
+        poll_timeout_ms(Duration::ZERO),
+        0,
+        "Zero timeout doesn't return right away"
+    );
+    assert_eq!(
+        poll_timeout_ms(Duration::from_micros(1)),
+        1,
+        "Short timeout isn't rounded up"
+    );
+    assert_eq!(
+        poll_timeout_ms(Duration::from_millis(2)),
+        2,
+        "Timeout isn't exact"
+    );
+    assert_eq!(
+        poll_timeout_ms(Duration::from_micros(2001)),
+        3,
+        "Timeout isn't rounded up"
+    );
+    assert_eq!(
+        poll_timeout_ms(Duration::MAX),
+        i32::MAX,
+        "Long timeout isn't clamped"
+    );
+}
+
+#[test]
+fn set_name_too_long() {
+    let buf = DmaBuf::from(memfd(b"name"));
+    let name = "a".repeat(32);
+
+    let Err(MapError::InvalidName { name: rejected, .. }) = buf.set_name(&name) else {
+        panic!("Setting a 32 bytes name didn't fail as expected");
+    };
+    assert_eq!(rejected, name, "Unexpected name in the error");
+
+    // The longest name allowed gets through to the ioctl, which a memfd doesn't implement.
+    let Err(MapError::Unsupported { .. }) = buf.set_name(&name[..31]) else {
+        panic!("Setting a 31 bytes name didn't reach the ioctl");
+    };
+}
+
+#[test]
+fn set_name_nul_byte() {
+    let buf = DmaBuf::from(memfd(b"name"));
+
+    let Err(MapError::InvalidName { name, .. }) = buf.set_name("na\0me") else {
+        panic!("Setting a name with a NUL byte didn't fail as expected");
+    };
+    assert_eq!(name, "na\0me", "Unexpected name in the error");
+}
+
+#[test]
+fn heap_open_rejects_paths() {
+    for name in ["", ".", "..", "../system", "system/", "/dev/null"] {
+        let Err(MapError::InvalidName { name: rejected, .. }) = DmaHeap::open(name) else {
+            panic!("Opening heap {name:?} didn't fail as expected");
+        };
+        assert_eq!(rejected, name, "Unexpected name in the error");
+    }
+}
+
+// Each wait used to register the buffer file descriptor itself, and the reactor rejected any
+// concurrent wait with EEXIST.
+#[cfg(feature = "tokio")]
+#[test]
+fn concurrent_readable() {
+    use core::{
+        future::{poll_fn, Future as _},
+        pin::pin,
+        task::Poll,
+    };
+
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_io()
+        .build()
+        .expect("Couldn't create the runtime");
+
+    // A pipe isn't readable until it's written to, which keeps both waits pending.
+    let (reader, writer) = rustix::pipe::pipe().expect("Couldn't create a pipe");
+    let buf = DmaBuf::from(reader);
+
+    runtime.block_on(async {
+        let mut first = pin!(buf.readable());
+        let mut second = pin!(buf.readable());
+
+        poll_fn(|cx| {
+            assert!(
+                first.as_mut().poll(cx).is_pending(),
+                "First wait didn't block"
+            );
+            assert!(
+                second.as_mut().poll(cx).is_pending(),
+                "Second wait didn't block"
+            );
+            Poll::Ready(())
+        })
+        .await;
+
+        rustix::io::write(&writer, b"ready").expect("Couldn't write to the pipe");
+
+        first.await.expect("First wait failed");
+        second.await.expect("Second wait failed");
+    });
+}
@@ -0,0 +1,111 @@
+// Copyright 2020-2021, Cerno
+// Licensed under the MIT License
+// See the LICENSE file or <http://opensource.org/licenses/MIT>
+
+use rustix::param::page_size;
+
+use crate::{checked_subrange, DmaBuf, MapError, MappedDmaBufRo};
+
+/// A sliding, read-only, window over a [`DmaBuf`]
+///
+/// Mapping a whole buffer can use more virtual memory than desirable for very large buffers. A
+/// `WindowedMapping` only maps a fixed-size part of the buffer at a time, and
+/// [`WindowedMapping::seek`] moves that window to give access to any other part of the buffer,
+/// without any copy.
+///
+/// Each window is a [`MappedDmaBufRo`], so the CPU read access starts when a window is mapped, and
+/// ends when it's unmapped.
+#[derive(Debug)]
+pub struct WindowedMapping<'a> {
+    buf: &'a DmaBuf,
+    window_len: usize,
+    window_offset: usize,
+    offset: usize,
+    mapping: Option<MappedDmaBufRo<'a>>,
+}
+
+impl<'a> WindowedMapping<'a> {
+    /// Creates a new `WindowedMapping` over `buf`, with its window at the start of the buffer
+    ///
+    /// `window_len` is rounded up to a multiple of the page size, and is at least one page.
+    ///
+    /// # Errors
+    ///
+    /// Will return [`MapError::TooLarge`] if `window_len` can't be rounded up to a multiple of the
+    /// page size, or an error if the buffer's length can't be retrieved, or if the first window
+    /// can't be mapped.
+    pub fn new(buf: &'a DmaBuf, window_len: usize) -> Result<Self, MapError> {
+        let Some(window_len) = window_len.max(1).checked_next_multiple_of(page_size()) else {
+            return Err(MapError::TooLarge {
+                size: u64::try_from(window_len)?,
+            });
+        };
+
+        let mut windowed = Self {
+            buf,
+            window_len,
+            window_offset: 0,
+            offset: 0,
+            mapping: None,
+        };
+
+        windowed.map_window(0)?;
+
+        Ok(windowed)
+    }
+
+    fn map_window(&mut self, offset: usize) -> Result<(), MapError> {
+        let size = self.buf.len()?;
+        checked_subrange(offset, 1, size)?;
+
+        if let Some(mapping) = self.mapping.take() {
+            mapping.release()?;
+        }
+
+        let alignment = self.buf.offset_alignment();
+        let window_offset = (offset / alignment) * alignment;
+        let len = self.window_len.min(size - window_offset);
+
+        self.mapping = Some(self.buf.memory_map_ro_range(window_offset, len)?);
+        self.window_offset = window_offset;
+        self.offset = offset;
+
+        Ok(())
+    }
+
+    /// Moves the window to cover `offset` in the buffer
+    ///
+    /// The current window is unmapped, and a new one, starting on the page `offset` is in, is
+    /// mapped. If `offset` is already within the current window, nothing is remapped.
+    ///
+    /// # Errors
+    ///
+    /// Will return [`MapError::RangeOutOfBounds`] if `offset` is past the end of the buffer, or an
+    /// error if either the current window can't be released or the new one can't be mapped. The
+    /// window is left unmapped in the latter case, and [`WindowedMapping::as_slice`] returns an
+    /// empty slice until a later call succeeds.
+    pub fn seek(&mut self, offset: usize) -> Result<(), MapError> {
+        if let Some(mapping) = &self.mapping {
+            if (self.window_offset..self.window_offset + mapping.len).contains(&offset) {
+                self.offset = offset;
+                return Ok(());
+            }
+        }
+
+        self.map_window(offset)
+    }
+
+    /// Returns the offset in the buffer the window has last been moved to
+    #[must_use]
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// Returns the content of the buffer from the current offset to the end of the window
+    #[must_use]
+    pub fn as_slice(&self) -> &[u8] {
+        self.mapping.as_ref().map_or(&[], |mapping| {
+            &mapping.as_slice()[self.offset - self.window_offset..]
+        })
+    }
+}